@@ -1,13 +1,13 @@
 use asciime_filter::{
-    charset, AsciiFilter, AsciiMap, AsciiMode, Frame, FrameFilter, GlyphMapBuilder,
+    charset, AsciiFilter, AsciiMap, AsciiMode, Frame, FrameFilter, GlyphMapBuilder, PixelFormat,
 };
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 
 pub fn ascii_filter_bench(c: &mut Criterion) {
     let chars = charset(6).unwrap();
     let glyphs = GlyphMapBuilder::new(&chars).build().unwrap();
-    let ascii_map = AsciiMap::new(chars);
-    let ascii_filter = AsciiFilter::new(ascii_map, glyphs, AsciiMode::Color);
+    let ascii_map = AsciiMap::new(chars).unwrap();
+    let mut ascii_filter = AsciiFilter::new(ascii_map, glyphs, AsciiMode::Color);
 
     let width: u32 = 1280;
     let height: u32 = 720;
@@ -41,6 +41,17 @@ pub fn ascii_filter_bench(c: &mut Criterion) {
             BatchSize::SmallInput,
         )
     });
+    group.bench_function("arbitrary frame (NV12)", |b| {
+        let size = PixelFormat::Nv12.frame_size(width, height);
+        b.iter_batched(
+            || arbitrary_buf[..size].to_vec(),
+            |mut buf| {
+                let mut frame = Frame::with_format(&mut buf, width, height, PixelFormat::Nv12);
+                ascii_filter.process(&mut frame);
+            },
+            BatchSize::SmallInput,
+        )
+    });
     group.finish();
 }
 
@@ -0,0 +1,54 @@
+/// Shades of a whole cell, from the most ink to none.
+pub const SHADES: [char; 5] = ['█', '▓', '▒', '░', ' '];
+/// Half blocks by which of the top and bottom halves of a cell they fill,
+/// the top half being bit 0.
+pub const HALF_BLOCKS: [char; 4] = [' ', '▀', '▄', '█'];
+/// Quadrant blocks by which quadrants of a cell they fill, in reading order
+/// from bit 0.
+pub const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// Character sets of Unicode block elements, which fill their whole cell
+/// rather than a font's glyph box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockCharset {
+    /// Full, dark, medium and light shade, picked by brightness.
+    Shades,
+    /// Upper and lower half blocks, picked by the brightness of each half
+    /// of a cell, doubling the vertical resolution.
+    HalfBlocks,
+    /// Quadrant blocks, picked by the brightness of each quadrant of a cell,
+    /// doubling the resolution both ways.
+    Quadrants,
+}
+
+/// The coverage of each pixel of a `width` x `height` cell by the block
+/// element `c`, row by row, or `None` if `c` isn't one. Computed rather
+/// than rendered, since fonts draw these within their glyph box, which
+/// leaves gaps between cells.
+#[must_use]
+pub fn coverage(c: char, width: u32, height: u32) -> Option<Vec<u8>> {
+    let shade = match c {
+        '░' => Some(u8::MAX / 4),
+        '▒' => Some(u8::MAX / 2),
+        '▓' => Some(u8::MAX - u8::MAX / 4),
+        _ => None,
+    };
+    if let Some(shade) = shade {
+        return Some(vec![shade; (width * height) as usize]);
+    }
+    let quadrants = QUADRANTS.iter().position(|&q| q == c)?;
+    let pts = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let quadrant = usize::from(2 * x >= width) + 2 * usize::from(2 * y >= height);
+            if quadrants & (1 << quadrant) == 0 {
+                0
+            } else {
+                u8::MAX
+            }
+        })
+        .collect();
+    Some(pts)
+}
@@ -0,0 +1,64 @@
+/// The first braille pattern, with no dots raised.
+const BRAILLE_BLANK: u32 = 0x2800;
+/// The bit of each dot of a braille pattern's code, by row and column.
+/// Dots are numbered down the left column and then the right one, with the
+/// bottom row, added later, last.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0, 3], [1, 4], [2, 5], [6, 7]];
+/// The radius of drawn dots, as a fraction of the smaller side of their
+/// part of the cell.
+const DOT_RADIUS: f32 = 0.35;
+
+/// The braille patterns by which of their 2x4 dots are raised, in reading
+/// order from bit 0.
+#[must_use]
+pub fn patterns() -> Vec<char> {
+    (0..256_u32)
+        .map(|pattern| {
+            let code = BRAILLE_DOTS
+                .iter()
+                .flatten()
+                .enumerate()
+                .filter(|&(bit, _)| pattern & (1 << bit) != 0)
+                .fold(BRAILLE_BLANK, |code, (_, dot)| code | 1 << dot);
+            // The braille block is all assigned
+            char::from_u32(code).unwrap()
+        })
+        .collect()
+}
+
+/// The coverage of each pixel of a `width` x `height` cell by the dots of
+/// the braille pattern `c`, row by row, or `None` if `c` isn't one. For
+/// fonts without braille glyphs.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+#[must_use]
+pub fn coverage(c: char, width: u32, height: u32) -> Option<Vec<u8>> {
+    let code = u32::from(c)
+        .checked_sub(BRAILLE_BLANK)
+        .filter(|&code| code < 256)?;
+    let (dot_width, dot_height) = (width as f32 / 2.0, height as f32 / 4.0);
+    let radius = dot_width.min(dot_height) * DOT_RADIUS;
+    let pts = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+            let col = ((x / dot_width) as usize).min(1);
+            let row = ((y / dot_height) as usize).min(3);
+            let dot = BRAILLE_DOTS[row][col];
+            if code & (1 << dot) == 0 {
+                return 0;
+            }
+            let center = (
+                (col as f32 + 0.5) * dot_width,
+                (row as f32 + 0.5) * dot_height,
+            );
+            let distance = (x - center.0).hypot(y - center.1);
+            // Antialias the edge over a pixel
+            ((radius - distance + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect();
+    Some(pts)
+}
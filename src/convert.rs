@@ -0,0 +1,44 @@
+use rayon::prelude::*;
+
+use crate::pixfmt::Pixels;
+use crate::NSUBFRAME_SPLITS;
+
+/// Scale `src` to the size of `dst` with nearest-neighbor sampling and convert
+/// it to the `PixelFormat` of `dst`.
+pub fn convert(src: &Pixels<'_>, dst: &mut Pixels<'_>) {
+    if (src.width(), src.height(), src.format()) == (dst.width(), dst.height(), dst.format()) {
+        dst.copy_from(src);
+        return;
+    }
+
+    let xs = scale_coords(src.width(), dst.width());
+    let ys = scale_coords(src.height(), dst.height());
+    let mut subframes = dst.splitn(NSUBFRAME_SPLITS);
+    let offsets = subframes
+        .iter()
+        .scan(0, |y, subframe| {
+            let offset = *y;
+            *y += subframe.height();
+            Some(offset as usize)
+        })
+        .collect::<Vec<_>>();
+    subframes
+        .par_iter_mut()
+        .zip(offsets)
+        .for_each(|(subframe, offset)| {
+            let height = subframe.height() as usize;
+            for (y, &src_y) in (0..).zip(&ys[offset..offset + height]) {
+                for (x, &src_x) in (0..).zip(&xs) {
+                    subframe.set_yuv(x, y, src.get_yuv(src_x, src_y));
+                }
+            }
+        });
+}
+
+/// Map each destination coordinate to the nearest source coordinate.
+#[allow(clippy::cast_possible_truncation)]
+fn scale_coords(src_len: u32, dst_len: u32) -> Vec<u32> {
+    (0..u64::from(dst_len))
+        .map(|i| (i * u64::from(src_len) / u64::from(dst_len)) as u32)
+        .collect()
+}
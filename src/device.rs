@@ -0,0 +1,260 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use anyhow::{anyhow, Context};
+use itertools::Itertools;
+use v4l::{
+    buffer::Type,
+    format::fourcc::FourCC,
+    frameinterval::FrameIntervalEnum,
+    io::traits::{CaptureStream, OutputStream},
+    prelude::*,
+    video::{
+        capture::Parameters as CaptureParameters, output::Parameters as OutputParameters, Capture,
+        Output,
+    },
+    Capabilities, Format, Fraction,
+};
+
+use crate::{mjpeg, CaptureFormat, Frame, FrameSink, FrameSource, PixelFormat};
+
+/// Captures frames from a V4L2 device through a memory mapped stream.
+pub struct DeviceSource<'cap> {
+    stream: MmapStream<'cap>,
+    caps: Capabilities,
+    fmt: Format,
+    params: CaptureParameters,
+    cap_format: CaptureFormat,
+}
+
+impl DeviceSource<'_> {
+    /// Open the capture device at `path` and pick the fastest supported
+    /// format at its current frame size.
+    pub fn new(path: &str) -> anyhow::Result<Self> {
+        let cap = Device::with_path(path).context("Failed to open capture device")?;
+
+        let mut fmt = Capture::format(&cap).context("Failed to read capture format")?;
+        let (cap_format, interval) = negotiate_format(&cap, fmt.width, fmt.height)?;
+        fmt.fourcc = cap_format.fourcc();
+        let fmt = Capture::set_format(&cap, &fmt).context("Failed to set capture format")?;
+        let cap_format = CaptureFormat::try_from(fmt.fourcc)?;
+        if let Some(interval) = interval {
+            Capture::set_params(&cap, &CaptureParameters::new(interval))
+                .context("Failed to set capture parameters")?;
+        }
+        let params = Capture::params(&cap).context("Failed to read capture parameters")?;
+        let caps = cap
+            .query_caps()
+            .context("Failed to read capture capabilities")?;
+
+        let stream =
+            MmapStream::new(&cap, Type::VideoCapture).context("Failed to open capture stream")?;
+        Ok(Self {
+            stream,
+            caps,
+            fmt,
+            params,
+            cap_format,
+        })
+    }
+
+    #[must_use]
+    pub const fn cap_format(&self) -> CaptureFormat {
+        self.cap_format
+    }
+}
+
+impl FrameSource for DeviceSource<'_> {
+    fn width(&self) -> u32 {
+        self.fmt.width
+    }
+
+    fn height(&self) -> u32 {
+        self.fmt.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        // Compressed frames are decoded to YUYV
+        match self.cap_format {
+            CaptureFormat::Raw(format) => format,
+            CaptureFormat::Mjpeg => PixelFormat::Yuyv,
+        }
+    }
+
+    fn interval(&self) -> Fraction {
+        self.params.interval
+    }
+
+    fn read_frame(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        let (width, height) = (self.width(), self.height());
+        let (buf_in, meta_in) =
+            CaptureStream::next(&mut self.stream).context("Failed to read capture frame")?;
+        match self.cap_format {
+            CaptureFormat::Raw(_) => buf.copy_from_slice(
+                buf_in
+                    .get(..buf.len())
+                    .context("Capture frame is smaller than expected")?,
+            ),
+            CaptureFormat::Mjpeg => {
+                let len = (meta_in.bytesused as usize).min(buf_in.len());
+                mjpeg::decode(&buf_in[..len], buf, width, height)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl fmt::Display for DeviceSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.caps, self.fmt, self.params)
+    }
+}
+
+/// Outputs frames to a V4L2 device, e.g. v4l2loopback, through a memory
+/// mapped stream.
+pub struct DeviceSink<'out> {
+    stream: MmapStream<'out>,
+    caps: Capabilities,
+    fmt: Format,
+    params: OutputParameters,
+    format: PixelFormat,
+}
+
+impl DeviceSink<'_> {
+    /// Open the output device at `path` and configure it for `width` x
+    /// `height` frames in `format` at the given frame interval.
+    pub fn new(
+        path: &str,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        interval: Fraction,
+    ) -> anyhow::Result<Self> {
+        let out = Device::with_path(path).context("Failed to open output device")?;
+
+        // Configure output and confirm the settings are valid
+        let mut fmt = Output::format(&out).context("Failed to read output format")?;
+        fmt.width = width;
+        fmt.height = height;
+        fmt.fourcc = format.fourcc();
+        fmt.stride = 0;
+        fmt.size = 0;
+        let fmt = Output::set_format(&out, &fmt).context("Failed to set output format")?;
+        let params = Output::set_params(&out, &OutputParameters::new(interval))
+            .context("Failed to set output parameters")?;
+
+        if fmt.width != width
+            || fmt.height != height
+            || fmt.fourcc != format.fourcc()
+            || interval.numerator != params.interval.numerator
+            || interval.denominator != params.interval.denominator
+        {
+            return Err(anyhow!(
+                "Output device does not support the requested parameters \
+                 ({width}x{height} {format} at {interval} [s]):\n{fmt}{params}",
+            ));
+        }
+        let caps = out
+            .query_caps()
+            .context("Failed to read output capabilities")?;
+
+        let stream =
+            MmapStream::new(&out, Type::VideoOutput).context("Failed to open output stream")?;
+        Ok(Self {
+            stream,
+            caps,
+            fmt,
+            params,
+            format,
+        })
+    }
+}
+
+impl FrameSink for DeviceSink<'_> {
+    fn width(&self) -> u32 {
+        self.fmt.width
+    }
+
+    fn height(&self) -> u32 {
+        self.fmt.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn write_frame(&mut self, frame: &Frame<'_>) -> anyhow::Result<()> {
+        let (width, height, format) = (self.width(), self.height(), self.format());
+        let size = format.frame_size(width, height);
+        let (buf_out, meta_out) =
+            OutputStream::next(&mut self.stream).context("Failed to read output frame")?;
+        let buf_out = buf_out
+            .get_mut(..size)
+            .context("Output frame is smaller than expected")?;
+        let mut out_frame = Frame::with_format(buf_out, width, height, format);
+        frame.convert_into(&mut out_frame);
+
+        // Set metadata
+        // https://www.kernel.org/doc/html/v4.15/media/uapi/v4l/buffer.html#struct-v4l2-buffer
+        meta_out.field = 0;
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            meta_out.bytesused = size as u32;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceSink<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.caps, self.fmt, self.params)
+    }
+}
+
+/// Pick the `CaptureFormat` with the highest frame rate at the given size,
+/// breaking ties by order of preference. Also returns the frame interval
+/// if the device reports it.
+fn negotiate_format(
+    cap: &Device,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<(CaptureFormat, Option<Fraction>)> {
+    let fourccs = Capture::enum_formats(cap)
+        .context("Failed to read capture formats")?
+        .into_iter()
+        .map(|desc| desc.fourcc)
+        .collect::<Vec<_>>();
+    CaptureFormat::PREFERRED
+        .into_iter()
+        .filter(|fmt| fourccs.contains(&fmt.fourcc()))
+        .map(|fmt| (fmt, min_interval(cap, fmt.fourcc(), width, height)))
+        .min_by(|(_, ival1), (_, ival2)| match (ival1, ival2) {
+            (Some(ival1), Some(ival2)) => cmp_fraction(*ival1, *ival2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "No supported fourcc, capture device offers: {}",
+                fourccs.iter().join(", ")
+            )
+        })
+}
+
+fn min_interval(cap: &Device, fourcc: FourCC, width: u32, height: u32) -> Option<Fraction> {
+    Capture::enum_frameintervals(cap, fourcc, width, height)
+        .ok()?
+        .into_iter()
+        .map(|ival| match ival.interval {
+            FrameIntervalEnum::Discrete(ival) => ival,
+            FrameIntervalEnum::Stepwise(ival) => ival.min,
+        })
+        .min_by(|ival1, ival2| cmp_fraction(*ival1, *ival2))
+}
+
+fn cmp_fraction(frac1: Fraction, frac2: Fraction) -> Ordering {
+    let lhs = u64::from(frac1.numerator) * u64::from(frac2.denominator);
+    let rhs = u64::from(frac2.numerator) * u64::from(frac1.denominator);
+    lhs.cmp(&rhs)
+}
@@ -0,0 +1,199 @@
+use crate::Brightness;
+
+/// Thresholds for ordered dithering, in sixteenths.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+/// Where Floyd–Steinberg spreads a cell's error, as (x, y, weight).
+const FLOYD_STEINBERG: &[(i32, u32, f32)] = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+/// Where Atkinson spreads a cell's error. Only three quarters of it is kept,
+/// which keeps more contrast in light and dark areas.
+const ATKINSON: &[(i32, u32, f32)] = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+/// How the brightness of cells is quantized to the levels of the charset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dithering {
+    /// Cut each cell's brightness down to its level.
+    #[default]
+    None,
+    /// Diffuse each cell's quantization error to its right and lower
+    /// neighbours.
+    FloydSteinberg,
+    /// Diffuse part of each cell's quantization error a little further.
+    Atkinson,
+    /// Offset each cell by a threshold from a 4x4 Bayer matrix.
+    Bayer,
+}
+
+impl Dithering {
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::None => Self::FloydSteinberg,
+            Self::FloydSteinberg => Self::Atkinson,
+            Self::Atkinson => Self::Bayer,
+            Self::Bayer => Self::None,
+        }
+    }
+}
+
+/// The level out of `levels` of each of the `cols` x `rows` cells of
+/// `brightness`, row by row. When dithering, the darkest and brightest
+/// levels stand for black and white.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+pub fn quantize(
+    dithering: Dithering,
+    brightness: &[Brightness],
+    cols: u32,
+    rows: u32,
+    levels: usize,
+) -> Vec<usize> {
+    let max_level = levels.saturating_sub(1);
+    // Brightness per level step
+    let step = 255.0 / max_level.max(1) as f32;
+    let level = |value: f32| (value / step).round().clamp(0.0, max_level as f32) as usize;
+    match dithering {
+        Dithering::None => brightness
+            .iter()
+            .map(|b| usize::from(b.value()) * levels / 256)
+            .collect(),
+        Dithering::FloydSteinberg => diffuse(FLOYD_STEINBERG, brightness, cols, rows, step, level),
+        Dithering::Atkinson => diffuse(ATKINSON, brightness, cols, rows, step, level),
+        Dithering::Bayer => (0..rows)
+            .flat_map(|row| (0..cols).map(move |col| (col, row)))
+            .zip(brightness)
+            .map(|((col, row), b)| {
+                let threshold =
+                    (f32::from(BAYER_4X4[row as usize % 4][col as usize % 4]) + 0.5) / 16.0;
+                level(f32::from(b.value()) + (threshold - 0.5) * step)
+            })
+            .collect(),
+    }
+}
+
+/// Quantize cells in reading order, spreading each one's error to the
+/// following cells according to `kernel`.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn diffuse<F>(
+    kernel: &[(i32, u32, f32)],
+    brightness: &[Brightness],
+    cols: u32,
+    rows: u32,
+    step: f32,
+    level: F,
+) -> Vec<usize>
+where
+    F: Fn(f32) -> usize,
+{
+    let mut values = brightness
+        .iter()
+        .map(|b| f32::from(b.value()))
+        .collect::<Vec<_>>();
+    let mut levels = Vec::with_capacity(values.len());
+    for row in 0..rows {
+        for col in 0..cols {
+            let value = values[(row * cols + col) as usize];
+            let quantized = level(value);
+            let error = value - quantized as f32 * step;
+            for &(dx, dy, weight) in kernel {
+                let (x, y) = (col as i32 + dx, row + dy);
+                if (0..cols as i32).contains(&x) && y < rows {
+                    values[(y * cols + x as u32) as usize] += error * weight;
+                }
+            }
+            levels.push(quantized);
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+
+    const DITHERINGS: [Dithering; 3] = [
+        Dithering::FloydSteinberg,
+        Dithering::Atkinson,
+        Dithering::Bayer,
+    ];
+
+    /// A `cols` x `rows` grid of cells going from black in the left column
+    /// to white in the right one.
+    #[allow(clippy::cast_possible_truncation)]
+    fn ramp(cols: u32, rows: u32) -> Vec<Brightness> {
+        (0..rows)
+            .flat_map(|_| (0..cols).map(|col| Brightness((col * 255 / (cols - 1)) as u8)))
+            .collect()
+    }
+
+    /// The share of the cells at the brightest of two levels.
+    #[allow(clippy::cast_precision_loss)]
+    fn white_share(levels: &[usize]) -> f32 {
+        levels.iter().sum::<usize>() as f32 / levels.len() as f32
+    }
+
+    #[test]
+    fn flat_gray_is_half_white() {
+        let gray = vec![Brightness(128); 16 * 16];
+        for dithering in DITHERINGS {
+            let share = white_share(&quantize(dithering, &gray, 16, 16, 2));
+            assert!((share - 0.5).abs() < 0.05, "{dithering:?}: {share}");
+        }
+        // Without dithering every cell is cut down to the same level
+        let levels = quantize(Dithering::None, &gray, 16, 16, 2);
+        assert!(levels.iter().all(|&level| level == 1));
+    }
+
+    #[test]
+    fn black_and_white_stay_put() {
+        for dithering in DITHERINGS {
+            for (value, level) in [(0, 0), (255, 3)] {
+                let flat = vec![Brightness(value); 8 * 8];
+                let levels = quantize(dithering, &flat, 8, 8, 4);
+                assert!(levels.iter().all(|&l| l == level), "{dithering:?}: {value}");
+            }
+        }
+    }
+
+    #[test]
+    fn ramp_brightens_left_to_right() {
+        let (cols, rows) = (16, 32);
+        for dithering in DITHERINGS {
+            let levels = quantize(dithering, &ramp(cols, rows), cols, rows, 2);
+            // The share of white cells in the columns of `range`
+            let share = |range: Range<u32>| {
+                let cells = (0..rows * cols)
+                    .filter(|idx| range.contains(&(idx % cols)))
+                    .map(|idx| levels[idx as usize])
+                    .collect::<Vec<_>>();
+                white_share(&cells)
+            };
+            assert!(share(0..1) < f32::EPSILON, "{dithering:?}");
+            assert!(share(cols - 1..cols) > 1.0 - f32::EPSILON, "{dithering:?}");
+            // Each half of the ramp is mostly its own color
+            assert!(share(0..cols / 2) < 0.3, "{dithering:?}");
+            assert!(share(cols / 2..cols) > 0.7, "{dithering:?}");
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use itertools::Itertools;
+
+use crate::pixfmt::Pixels;
+
+/// The characters drawn along edges, whatever the charset.
+pub const EDGE_CHARS: [char; 5] = ['|', '/', '-', '\\', '_'];
+/// The average gradient magnitude over a cell above which it is drawn as an
+/// edge. A full contrast step across a 10 pixel cell averages about 200.
+const EDGE_THRESHOLD: f32 = 40.0;
+/// How consistently the gradients in a cell must point the same way, from 0
+/// for texture or corners to 1 for a straight edge.
+const MIN_COHERENCE: f32 = 0.4;
+/// Horizontal edges whose weight lies below this fraction of the cell's
+/// height are drawn as `_` rather than `-`.
+const UNDERSCORE_FROM: f32 = 0.7;
+
+/// Finds edges in a frame with a Sobel operator, repeating its border
+/// pixels so that every pixel has all its neighbours.
+#[derive(Debug)]
+pub struct EdgeDetector<'a> {
+    pixels: &'a Pixels<'a>,
+}
+
+impl<'a> EdgeDetector<'a> {
+    pub const fn new(pixels: &'a Pixels<'a>) -> Self {
+        Self { pixels }
+    }
+
+    /// The character for the edge through the `width` x `height` cell at
+    /// (`x`, `y`), or `None` if its gradients are too weak or disagree.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn edge(&self, x: u32, y: u32, width: u32, height: u32) -> Option<char> {
+        let width = width.min(self.pixels.width() - x);
+        let height = height.min(self.pixels.height() - y);
+        // Sums of the gradients' squared magnitude and doubled angle vector,
+        // so that opposite gradients along the same edge add up
+        let (mut energy, mut cos2, mut sin2) = (0.0, 0.0, 0.0);
+        let (mut magnitude, mut weighted_y) = (0.0, 0.0);
+        for (yoff, xoff) in (0..height).cartesian_product(0..width) {
+            let (gx, gy) = self.sobel(x + xoff, y + yoff);
+            let squared = gx * gx + gy * gy;
+            energy += squared;
+            cos2 += gx * gx - gy * gy;
+            sin2 += 2.0 * gx * gy;
+            let norm = squared.sqrt();
+            magnitude += norm;
+            weighted_y += norm * (yoff as f32 + 0.5);
+        }
+
+        let strength = magnitude / (width * height).max(1) as f32;
+        if strength < EDGE_THRESHOLD || cos2.hypot(sin2) < MIN_COHERENCE * energy {
+            return None;
+        }
+        // The gradient's direction in degrees, within (-90, 90], with y down
+        let angle = sin2.atan2(cos2).to_degrees() / 2.0;
+        let c = match angle {
+            a if a.abs() <= 22.5 => '|',
+            a if (22.5..67.5).contains(&a) => '/',
+            a if (-67.5..-22.5).contains(&a) => '\\',
+            _ if weighted_y / magnitude >= UNDERSCORE_FROM * height as f32 => '_',
+            _ => '-',
+        };
+        Some(c)
+    }
+
+    /// The horizontal and vertical gradient at (`x`, `y`).
+    fn sobel(&self, x: u32, y: u32) -> (f32, f32) {
+        let (width, height) = (self.pixels.width(), self.pixels.height());
+        let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+        let (above, below) = (y.saturating_sub(1), (y + 1).min(height - 1));
+        let luma = |x, y| f32::from(self.pixels.get_brightness(x, y).value());
+        let gx = (luma(right, above) + 2.0 * luma(right, y) + luma(right, below))
+            - (luma(left, above) + 2.0 * luma(left, y) + luma(left, below));
+        let gy = (luma(left, below) + 2.0 * luma(x, below) + luma(right, below))
+            - (luma(left, above) + 2.0 * luma(x, above) + luma(right, above));
+        (gx, gy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PixelFormat;
+
+    const SIZE: u32 = 10;
+
+    /// The edge found in a cell black where `dark` is true and white
+    /// elsewhere.
+    fn edge<F>(dark: F) -> Option<char>
+    where
+        F: Fn(u32, u32) -> bool,
+    {
+        let format = PixelFormat::Rgb24;
+        let mut buf = vec![0; format.frame_size(SIZE, SIZE)];
+        for (idx, px) in (0..).zip(buf.chunks_exact_mut(3)) {
+            px.fill(if dark(idx % SIZE, idx / SIZE) { 0 } else { 255 });
+        }
+        let pixels = Pixels::new(&mut buf, SIZE, SIZE, format);
+        EdgeDetector::new(&pixels).edge(0, 0, SIZE, SIZE)
+    }
+
+    #[test]
+    fn vertical_edge_is_pipe() {
+        assert_eq!(edge(|x, _| x < SIZE / 2), Some('|'));
+        assert_eq!(edge(|x, _| x >= SIZE / 2), Some('|'));
+    }
+
+    #[test]
+    fn horizontal_edges() {
+        assert_eq!(edge(|_, y| y < SIZE / 2), Some('-'));
+        assert_eq!(edge(|_, y| y < SIZE - 2), Some('_'));
+    }
+
+    #[test]
+    fn diagonal_edges() {
+        // Dark above the diagonal from the bottom left, then from the top left
+        assert_eq!(edge(|x, y| x + y < SIZE), Some('/'));
+        assert_eq!(edge(|x, y| x > y), Some('\\'));
+    }
+
+    #[test]
+    fn flat_cell_has_no_edge() {
+        assert_eq!(edge(|_, _| false), None);
+        assert_eq!(edge(|_, _| true), None);
+    }
+}
@@ -0,0 +1,309 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use crate::{AsciiCell, AsciiFilter, AsciiGrid, Frame, FrameSink, PixelFormat};
+
+const FALLBACK_FONT_FAMILY: &str = "monospace";
+const BACKGROUND: &str = "#000000";
+const FOREGROUND: &str = "#ffffff";
+
+/// A self-contained document format for `AsciiGrid`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    /// A `<pre>` of colored `<span>`s.
+    Html,
+    /// A `<text>` per row with every character placed in its cell.
+    Svg,
+}
+
+impl DocumentFormat {
+    #[must_use]
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+/// Renders `AsciiGrid`s as documents, drawing each character in its cell's
+/// color, or white for grayscale grids, on a black background. Characters
+/// are sized to the grid's cells.
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    font_family: String,
+}
+
+impl Exporter {
+    /// Use `font_family`, falling back to any monospace font.
+    #[must_use]
+    pub fn new(font_family: Option<&str>) -> Self {
+        // Keep the name safe to quote in CSS and XML attributes
+        let font_family = font_family
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !matches!(c, '"' | '\'' | '\\' | '<' | '>' | '&' | ';'))
+            .collect::<String>();
+        Self { font_family }
+    }
+
+    fn font_stack(&self) -> String {
+        if self.font_family.is_empty() {
+            FALLBACK_FONT_FAMILY.into()
+        } else {
+            format!("'{}', {FALLBACK_FONT_FAMILY}", self.font_family)
+        }
+    }
+
+    #[must_use]
+    pub fn export(&self, grid: &AsciiGrid, format: DocumentFormat) -> String {
+        match format {
+            DocumentFormat::Html => self.html(grid),
+            DocumentFormat::Svg => self.svg(grid),
+        }
+    }
+
+    /// An HTML page with the grid in a `<pre>`. Letter spacing pads each
+    /// character to the cell width, assuming a monospace font.
+    #[must_use]
+    pub fn html(&self, grid: &AsciiGrid) -> String {
+        let (cell_width, cell_height) = grid.cell_size();
+        let mut doc = String::new();
+        doc.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        doc.push_str("<title>asciime</title>\n<style>\n");
+        let _ = writeln!(doc, "body {{ margin: 0; background: {BACKGROUND}; }}");
+        let _ = writeln!(
+            doc,
+            "pre {{ margin: 0; color: {FOREGROUND}; font-family: {}; font-size: {cell_height}px; \
+             line-height: {cell_height}px; letter-spacing: calc({cell_width}px - 1ch); }}",
+            self.font_stack()
+        );
+        doc.push_str("</style>\n</head>\n<body>\n<pre>");
+        for row in grid.iter_rows() {
+            for run in color_runs(row) {
+                let text = run.iter().map(|cell| cell.c).collect::<String>();
+                match run[0].rgb {
+                    Some(rgb) => {
+                        let _ = write!(
+                            doc,
+                            "<span style=\"color: {}\">{}</span>",
+                            hex(rgb),
+                            escape(&text)
+                        );
+                    }
+                    None => doc.push_str(&escape(&text)),
+                }
+            }
+            doc.push('\n');
+        }
+        doc.push_str("</pre>\n</body>\n</html>\n");
+        doc
+    }
+
+    /// An SVG image the size of the grid. Spaces are left out since every
+    /// character is positioned explicitly.
+    #[must_use]
+    pub fn svg(&self, grid: &AsciiGrid) -> String {
+        let (cell_width, cell_height) = grid.cell_size();
+        let (width, height) = (grid.cols() * cell_width, grid.rows() * cell_height);
+        let mut doc = String::new();
+        let _ = writeln!(
+            doc,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\">"
+        );
+        let _ = writeln!(
+            doc,
+            "<rect width=\"{width}\" height=\"{height}\" fill=\"{BACKGROUND}\"/>"
+        );
+        let _ = writeln!(
+            doc,
+            "<g font-family=\"{}\" font-size=\"{cell_height}\" fill=\"{FOREGROUND}\" \
+             xml:space=\"preserve\">",
+            self.font_stack()
+        );
+        for (row, cells) in (0..).zip(grid.iter_rows()) {
+            let mut spans = String::new();
+            let mut col = 0;
+            for run in color_runs(cells) {
+                #[allow(clippy::cast_possible_truncation)]
+                let end = col + run.len() as u32;
+                let (xs, text): (Vec<_>, String) = (col..end)
+                    .zip(run)
+                    .filter(|(_, cell)| cell.c != ' ')
+                    .map(|(col, cell)| ((col * cell_width).to_string(), cell.c))
+                    .unzip();
+                col = end;
+                if text.is_empty() {
+                    continue;
+                }
+                let fill = run[0]
+                    .rgb
+                    .map(|rgb| format!(" fill=\"{}\"", hex(rgb)))
+                    .unwrap_or_default();
+                let _ = write!(
+                    spans,
+                    "<tspan x=\"{}\"{fill}>{}</tspan>",
+                    xs.join(" "),
+                    escape(&text)
+                );
+            }
+            if !spans.is_empty() {
+                let _ = writeln!(
+                    doc,
+                    "<text y=\"{}\" dominant-baseline=\"text-before-edge\">{spans}</text>",
+                    row * cell_height
+                );
+            }
+        }
+        doc.push_str("</g>\n</svg>\n");
+        doc
+    }
+}
+
+/// Writes each frame as a document to `path`, replacing the previous one.
+pub struct DocumentSink<'font> {
+    path: PathBuf,
+    exporter: Exporter,
+    document: DocumentFormat,
+    ascii_filter: AsciiFilter<'font>,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    buf: Vec<u8>,
+}
+
+impl<'font> DocumentSink<'font> {
+    /// Map `width` x `height` frames to characters with `ascii_filter`'s
+    /// charset, glyph size, mode and font.
+    pub fn new<P>(
+        path: P,
+        document: DocumentFormat,
+        ascii_filter: AsciiFilter<'font>,
+        width: u32,
+        height: u32,
+    ) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        let exporter = Exporter::new(ascii_filter.font_family());
+        // Colors are averaged over each cell, so keep full chroma
+        let format = PixelFormat::Rgb24;
+        Self {
+            path: path.into(),
+            exporter,
+            document,
+            ascii_filter,
+            width,
+            height,
+            format,
+            buf: vec![0; format.frame_size(width, height)],
+        }
+    }
+}
+
+impl FrameSink for DocumentSink<'_> {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn write_frame(&mut self, frame: &Frame<'_>) -> anyhow::Result<()> {
+        let mut doc_frame = Frame::with_format(&mut self.buf, self.width, self.height, self.format);
+        frame.convert_into(&mut doc_frame);
+        let (cell_width, cell_height) = self.ascii_filter.size();
+        let grid = self
+            .ascii_filter
+            .to_grid(&doc_frame, cell_width, cell_height);
+        fs::write(&self.path, self.exporter.export(&grid, self.document)).with_context(|| {
+            format!(
+                "Failed to write {} file {}",
+                self.document.extension(),
+                self.path.display()
+            )
+        })
+    }
+}
+
+/// Split a row into runs of cells of the same color.
+fn color_runs(row: &[AsciiCell]) -> impl Iterator<Item = &[AsciiCell]> {
+    row.chunk_by(|cell1, cell2| cell1.rgb == cell2.rgb)
+}
+
+fn hex((red, green, blue): (u8, u8, u8)) -> String {
+    format!("#{red:02x}{green:02x}{blue:02x}")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Brightness;
+
+    /// A row of 8x16 cells spelling `<&" >`, in `rgb` if given.
+    fn grid(rgb: Option<(u8, u8, u8)>) -> AsciiGrid {
+        let cells = "<&\" >"
+            .chars()
+            .map(|c| AsciiCell {
+                c,
+                brightness: Brightness(255),
+                rgb,
+            })
+            .collect();
+        AsciiGrid::new(5, 1, 8, 16, cells)
+    }
+
+    #[test]
+    fn html_escapes_text() {
+        let exporter = Exporter::new(None);
+        assert!(exporter
+            .html(&grid(None))
+            .contains("<pre>&lt;&amp;\" &gt;\n</pre>"));
+        assert!(exporter
+            .html(&grid(Some((255, 0, 0))))
+            .contains("<pre><span style=\"color: #ff0000\">&lt;&amp;\" &gt;</span>\n</pre>"));
+    }
+
+    #[test]
+    fn svg_escapes_text() {
+        let exporter = Exporter::new(None);
+        assert!(exporter.svg(&grid(None)).contains(
+            "<text y=\"0\" dominant-baseline=\"text-before-edge\">\
+             <tspan x=\"0 8 16 32\">&lt;&amp;\"&gt;</tspan></text>\n"
+        ));
+        assert!(exporter
+            .svg(&grid(Some((0, 255, 0))))
+            .contains("<tspan x=\"0 8 16 32\" fill=\"#00ff00\">&lt;&amp;\"&gt;</tspan>"));
+    }
+
+    #[test]
+    fn font_family_is_quotable() {
+        let exporter = Exporter::new(Some("Evil\"' <Font>&;"));
+        assert_eq!(exporter.font_stack(), "'Evil Font', monospace");
+        let svg = exporter.svg(&grid(None));
+        assert!(svg.contains("<g font-family=\"'Evil Font', monospace\" font-size=\"16\""));
+        assert_eq!(Exporter::new(None).font_stack(), "monospace");
+    }
+}
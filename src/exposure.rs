@@ -0,0 +1,143 @@
+use crate::Brightness;
+
+/// How much each frame's histogram counts in the smoothed one. Lower values
+/// adapt more slowly but keep the picture from pumping.
+const HISTOGRAM_SMOOTHING: f32 = 0.1;
+/// The fraction of cells left clipped at either end when stretching, so
+/// that a few specular highlights or dead pixels don't set the range.
+const STRETCH_CLIP: f32 = 0.01;
+
+/// How the brightness of cells adapts to each frame's lighting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Exposure {
+    /// Map brightness as it is.
+    #[default]
+    Fixed,
+    /// Stretch the range of brightness in the frame to the full range.
+    Stretch,
+    /// Spread brightness so that every level is about as common.
+    Equalize,
+}
+
+impl Exposure {
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Fixed => Self::Stretch,
+            Self::Stretch => Self::Equalize,
+            Self::Equalize => Self::Fixed,
+        }
+    }
+}
+
+/// The histogram of cell brightness, smoothed over recent frames so that
+/// exposure follows changes in lighting gradually.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram(Option<[f32; 256]>);
+
+impl Histogram {
+    /// Add the brightness of a frame's cells, and return the mapping of
+    /// brightness `exposure` makes for the smoothed histogram.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn update(&mut self, exposure: Exposure, brightness: &[Brightness]) -> [u8; 256] {
+        let mut frame = [0.0; 256];
+        let weight = (brightness.len().max(1) as f32).recip();
+        for b in brightness {
+            frame[usize::from(b.value())] += weight;
+        }
+        let histogram = self.0.get_or_insert(frame);
+        for (bin, value) in histogram.iter_mut().zip(frame) {
+            *bin += HISTOGRAM_SMOOTHING * (value - *bin);
+        }
+
+        let mut lut = [0; 256];
+        match exposure {
+            Exposure::Fixed => {
+                for (value, dst) in (0_u8..=u8::MAX).zip(&mut lut) {
+                    *dst = value;
+                }
+            }
+            Exposure::Stretch => {
+                let (low, high) = percentiles(histogram, STRETCH_CLIP);
+                let range = f32::from(high.saturating_sub(low).max(1));
+                for (value, dst) in (0_u8..=u8::MAX).zip(&mut lut) {
+                    let level = (f32::from(value) - f32::from(low)) / range;
+                    *dst = (level * 255.0).round().clamp(0.0, 255.0) as u8;
+                }
+            }
+            Exposure::Equalize => {
+                // Map each level to the middle of its share of the cumulative
+                // distribution, so that a flat frame stays mid-gray
+                let total = histogram.iter().sum::<f32>().max(f32::EPSILON);
+                let mut below = 0.0;
+                for (bin, dst) in histogram.iter().zip(&mut lut) {
+                    let level = (below + bin / 2.0) / total;
+                    *dst = (level * 255.0).round().clamp(0.0, 255.0) as u8;
+                    below += bin;
+                }
+            }
+        }
+        lut
+    }
+}
+
+/// The brightness below which `clip` of the histogram lies, and the one above
+/// which `clip` of it lies.
+fn percentiles(histogram: &[f32; 256], clip: f32) -> (u8, u8) {
+    let total = histogram.iter().sum::<f32>();
+    let level = |fraction: f32| {
+        let mut below = 0.0;
+        (0_u8..=u8::MAX)
+            .zip(histogram)
+            .find(|(_, bin)| {
+                below += *bin;
+                below >= fraction * total
+            })
+            .map_or(u8::MAX, |(level, _)| level)
+    };
+    (level(clip), level(1.0 - clip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_is_identity() {
+        let lut = Histogram::default().update(Exposure::Fixed, &[Brightness(10)]);
+        assert!((0..=u8::MAX).all(|value| lut[usize::from(value)] == value));
+    }
+
+    #[test]
+    fn stretch_fills_the_range() {
+        let brightness = (100..=150).map(Brightness).collect::<Vec<_>>();
+        let lut = Histogram::default().update(Exposure::Stretch, &brightness);
+        assert_eq!((lut[0], lut[100], lut[150], lut[255]), (0, 0, 255, 255));
+        assert_eq!(lut[125], 128);
+    }
+
+    #[test]
+    fn stretch_constant_frame() {
+        let lut = Histogram::default().update(Exposure::Stretch, &[Brightness(100); 64]);
+        assert!(lut[..=100].iter().all(|&value| value == 0));
+        assert!(lut[101..].iter().all(|&value| value == 255));
+    }
+
+    #[test]
+    fn empty_frame() {
+        for exposure in [Exposure::Stretch, Exposure::Equalize] {
+            let lut = Histogram::default().update(exposure, &[]);
+            assert_eq!(lut[0], 0, "{exposure:?}");
+        }
+    }
+
+    #[test]
+    fn equalize_keeps_flat_frame_mid_gray() {
+        let lut = Histogram::default().update(Exposure::Equalize, &[Brightness(30); 64]);
+        assert_eq!(lut[30], 128);
+    }
+}
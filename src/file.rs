@@ -0,0 +1,501 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use v4l::Fraction;
+
+use crate::pacer::Pacer;
+use crate::pixfmt::yuv_to_rgb;
+use crate::{Frame, FrameSink, FrameSource, PixelFormat};
+
+const Y4M_MAGIC: &str = "YUV4MPEG2";
+const Y4M_FRAME: &str = "FRAME";
+const Y4M_NEUTRAL_CHROMA: u8 = 128;
+
+/// The chroma layout of the frames in a Y4M file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Y4mColorspace {
+    C420,
+    C422,
+    C444,
+    Mono,
+}
+
+impl Y4mColorspace {
+    fn parse(tag: &str) -> anyhow::Result<Self> {
+        match tag {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => Ok(Self::C420),
+            "422" => Ok(Self::C422),
+            "444" => Ok(Self::C444),
+            "mono" => Ok(Self::Mono),
+            _ => Err(anyhow!("Unsupported Y4M colorspace: {tag}")),
+        }
+    }
+
+    /// The colorspace frames in `format` are written as.
+    const fn for_format(format: PixelFormat) -> Self {
+        match format {
+            PixelFormat::Nv12 | PixelFormat::Nv21 | PixelFormat::I420 | PixelFormat::Yv12 => {
+                Self::C420
+            }
+            PixelFormat::Yuyv => Self::C422,
+            PixelFormat::Rgb24 | PixelFormat::Bgr24 | PixelFormat::Rgba32 | PixelFormat::Xrgb32 => {
+                Self::C444
+            }
+        }
+    }
+
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::C420 => "420jpeg",
+            Self::C422 => "422",
+            Self::C444 => "444",
+            Self::Mono => "mono",
+        }
+    }
+
+    /// The `PixelFormat` frames are converted to.
+    const fn format(self) -> PixelFormat {
+        match self {
+            Self::C420 | Self::Mono => PixelFormat::I420,
+            Self::C422 => PixelFormat::Yuyv,
+            Self::C444 => PixelFormat::Rgb24,
+        }
+    }
+
+    /// Horizontal and vertical chroma subsampling factors.
+    const fn subsampling(self) -> (u32, u32) {
+        match self {
+            Self::C420 | Self::Mono => (2, 2),
+            Self::C422 => (2, 1),
+            Self::C444 => (1, 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Container {
+    Y4m(Y4mColorspace),
+    Raw,
+}
+
+/// Reads frames from a YUV4MPEG2 (.y4m) or raw headerless file at the file's
+/// frame rate.
+#[derive(Debug)]
+pub struct FileSource {
+    path: PathBuf,
+    reader: BufReader<File>,
+    container: Container,
+    data_start: u64,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    interval: Fraction,
+    looping: bool,
+    pacer: Pacer,
+    scratch: Vec<u8>,
+}
+
+impl FileSource {
+    /// Open a YUV4MPEG2 file. The size, frame rate and format are read from
+    /// the stream header.
+    pub fn y4m<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut reader = open(path)?;
+        let header = read_line(&mut reader)?.context("Missing Y4M header")?;
+        let mut params = header.split(' ');
+        if params.next() != Some(Y4M_MAGIC) {
+            return Err(anyhow!("{} is not a Y4M file", path.display()));
+        }
+
+        let (mut width, mut height) = (None, None);
+        let mut interval = Fraction::new(1, 30);
+        let mut colorspace = Y4mColorspace::C420;
+        for param in params.filter(|param| !param.is_empty()) {
+            let (tag, val) = param.split_at_checked(1).context("Invalid Y4M header")?;
+            match tag {
+                "W" => width = Some(val.parse().context("Invalid Y4M width")?),
+                "H" => height = Some(val.parse().context("Invalid Y4M height")?),
+                "F" => {
+                    let (num, den) = val.split_once(':').context("Invalid Y4M frame rate")?;
+                    // The interval is the inverse of the frame rate
+                    interval = Fraction::new(
+                        den.parse().context("Invalid Y4M frame rate")?,
+                        num.parse().context("Invalid Y4M frame rate")?,
+                    );
+                }
+                "C" => colorspace = Y4mColorspace::parse(val)?,
+                "I" if val != "p" && val != "?" => {
+                    return Err(anyhow!("Interlaced Y4M files are not supported"));
+                }
+                // Ignore the pixel aspect ratio, interlacing and extensions
+                _ => {}
+            }
+        }
+        let width = width.context("Missing Y4M width")?;
+        let height = height.context("Missing Y4M height")?;
+        if interval.numerator == 0 || interval.denominator == 0 {
+            return Err(anyhow!("Invalid Y4M frame rate"));
+        }
+
+        let data_start = reader
+            .stream_position()
+            .context("Failed to read Y4M file")?;
+        Ok(Self {
+            path: path.into(),
+            reader,
+            container: Container::Y4m(colorspace),
+            data_start,
+            width,
+            height,
+            format: colorspace.format(),
+            interval,
+            looping: false,
+            pacer: Pacer::new(interval),
+            // Planar 4:2:2 and 4:4:4 are read into a separate buffer to be
+            // packed
+            scratch: match colorspace {
+                Y4mColorspace::C422 => vec![0; 2 * (width * height) as usize],
+                Y4mColorspace::C444 => vec![0; 3 * (width * height) as usize],
+                Y4mColorspace::C420 | Y4mColorspace::Mono => vec![],
+            },
+        })
+    }
+
+    /// Open a raw file of back-to-back `width` x `height` frames in `format`.
+    pub fn raw<P>(
+        path: P,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        interval: Fraction,
+    ) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        Ok(Self {
+            path: path.into(),
+            reader: open(path)?,
+            container: Container::Raw,
+            data_start: 0,
+            width,
+            height,
+            format,
+            interval,
+            looping: false,
+            pacer: Pacer::new(interval),
+            scratch: vec![],
+        })
+    }
+
+    /// Restart from the first frame instead of stopping at the end of the
+    /// file.
+    #[must_use]
+    pub const fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn try_read_frame(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        let buf = &mut buf[..self.format.frame_size(self.width, self.height)];
+        let colorspace = match self.container {
+            Container::Raw => return read_exact_or_eof(&mut self.reader, buf),
+            Container::Y4m(colorspace) => colorspace,
+        };
+
+        let Some(header) = read_line(&mut self.reader)? else {
+            return Ok(false);
+        };
+        if !header.starts_with(Y4M_FRAME) {
+            return Err(anyhow!("Invalid Y4M frame header: {header}"));
+        }
+        let complete = match colorspace {
+            Y4mColorspace::C420 => read_exact_or_eof(&mut self.reader, buf)?,
+            Y4mColorspace::Mono => {
+                let (luma, chroma) = buf.split_at_mut((self.width * self.height) as usize);
+                chroma.fill(Y4M_NEUTRAL_CHROMA);
+                read_exact_or_eof(&mut self.reader, luma)?
+            }
+            Y4mColorspace::C422 => {
+                let complete = read_exact_or_eof(&mut self.reader, &mut self.scratch)?;
+                self.pack_422(buf);
+                complete
+            }
+            Y4mColorspace::C444 => {
+                let complete = read_exact_or_eof(&mut self.reader, &mut self.scratch)?;
+                self.pack_444(buf);
+                complete
+            }
+        };
+        if complete {
+            Ok(true)
+        } else {
+            Err(anyhow!("Truncated frame"))
+        }
+    }
+
+    /// Interleave planar 4:2:2 into YUYV.
+    fn pack_422(&self, buf: &mut [u8]) {
+        let luma_len = (self.width * self.height) as usize;
+        let chroma_len = luma_len / 2;
+        let (luma, chroma) = self.scratch.split_at(luma_len);
+        let (u, v) = chroma.split_at(chroma_len);
+        for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+            px.copy_from_slice(&[luma[2 * i], u[i], luma[2 * i + 1], v[i]]);
+        }
+    }
+
+    /// Convert planar 4:4:4 into RGB24.
+    fn pack_444(&self, buf: &mut [u8]) {
+        let plane_len = (self.width * self.height) as usize;
+        let (luma, chroma) = self.scratch.split_at(plane_len);
+        let (u, v) = chroma.split_at(plane_len);
+        for (i, px) in buf.chunks_exact_mut(3).enumerate() {
+            let (red, green, blue) = yuv_to_rgb(luma[i], u[i], v[i]);
+            px.copy_from_slice(&[red, green, blue]);
+        }
+    }
+}
+
+impl FrameSource for FileSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn interval(&self) -> Fraction {
+        self.interval
+    }
+
+    /// Wait for the next frame to be due and read it into `buf`. Returns
+    /// `false` at the end of the file.
+    fn read_frame(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        self.pacer.wait();
+        if self.try_read_frame(buf)? {
+            return Ok(true);
+        }
+        if !self.looping {
+            return Ok(false);
+        }
+        self.reader
+            .seek(SeekFrom::Start(self.data_start))
+            .with_context(|| format!("Failed to rewind {}", self.path.display()))?;
+        if self.try_read_frame(buf)? {
+            Ok(true)
+        } else {
+            Err(anyhow!("{} contains no frames", self.path.display()))
+        }
+    }
+}
+
+impl fmt::Display for FileSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "file           : {}", self.path.display())?;
+        writeln!(f, "width          : {}", self.width)?;
+        writeln!(f, "height         : {}", self.height)?;
+        writeln!(f, "fourcc         : {}", self.format)?;
+        writeln!(f, "interval       : {} [s]", self.interval)
+    }
+}
+
+/// Writes frames to a YUV4MPEG2 (.y4m) or raw headerless file.
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    colorspace: Option<Y4mColorspace>,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    buf: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+impl FileSink {
+    /// Create a YUV4MPEG2 file of `width` x `height` frames at the given
+    /// frame interval. Frames in `format` are written as planar 4:2:0, 4:2:2
+    /// or, for RGB formats, 4:4:4.
+    pub fn y4m<P>(
+        path: P,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        interval: Fraction,
+    ) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let colorspace = Y4mColorspace::for_format(format);
+        let mut writer = create(path)?;
+        // The frame rate is the inverse of the interval
+        writeln!(
+            writer,
+            "{Y4M_MAGIC} W{width} H{height} F{}:{} Ip A1:1 C{}",
+            interval.denominator,
+            interval.numerator,
+            colorspace.tag(),
+        )
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Self {
+            path: path.into(),
+            writer,
+            colorspace: Some(colorspace),
+            width,
+            height,
+            format,
+            buf: vec![0; format.frame_size(width, height)],
+            scratch: vec![],
+        })
+    }
+
+    /// Create a raw file of back-to-back `width` x `height` frames in
+    /// `format`.
+    pub fn raw<P>(path: P, width: u32, height: u32, format: PixelFormat) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        Ok(Self {
+            path: path.into(),
+            writer: create(path)?,
+            colorspace: None,
+            width,
+            height,
+            format,
+            buf: vec![0; format.frame_size(width, height)],
+            scratch: vec![],
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Split `frame` into the Y, U and V planes of `colorspace`.
+    fn pack_planar(scratch: &mut Vec<u8>, frame: &Frame<'_>, colorspace: Y4mColorspace) {
+        let (width, height) = (frame.width(), frame.height());
+        let (hsub, vsub) = colorspace.subsampling();
+        scratch.clear();
+        scratch.extend(
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| frame.pixels.get_yuv(x, y).0),
+        );
+        for chroma in [|(_, u, _): (u8, u8, u8)| u, |(_, _, v): (u8, u8, u8)| v] {
+            scratch.extend(
+                (0..height)
+                    .step_by(vsub as usize)
+                    .flat_map(|y| (0..width).step_by(hsub as usize).map(move |x| (x, y)))
+                    .map(|(x, y)| chroma(frame.pixels.get_yuv(x, y))),
+            );
+        }
+    }
+}
+
+impl FrameSink for FileSink {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Convert `frame` and append it to the file.
+    fn write_frame(&mut self, frame: &Frame<'_>) -> anyhow::Result<()> {
+        let mut out_frame = Frame::with_format(&mut self.buf, self.width, self.height, self.format);
+        frame.convert_into(&mut out_frame);
+        if let Some(colorspace) = self.colorspace {
+            Self::pack_planar(&mut self.scratch, &out_frame, colorspace);
+            writeln!(self.writer, "{Y4M_FRAME}").and_then(|()| self.writer.write_all(&self.scratch))
+        } else {
+            self.writer.write_all(&self.buf)
+        }
+        // Flush every frame so the file only ever holds whole frames
+        .and_then(|()| self.writer.flush())
+        .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+impl fmt::Display for FileSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "file           : {}", self.path.display())?;
+        writeln!(f, "width          : {}", self.width)?;
+        writeln!(f, "height         : {}", self.height)?;
+        writeln!(f, "fourcc         : {}", self.format)
+    }
+}
+
+fn create(path: &Path) -> anyhow::Result<BufWriter<File>> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+fn open(path: &Path) -> anyhow::Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
+
+/// Read a newline terminated line, or `None` at the end of the file.
+fn read_line<R>(reader: &mut R) -> anyhow::Result<Option<String>>
+where
+    R: BufRead,
+{
+    let mut line = vec![];
+    if reader
+        .read_until(b'\n', &mut line)
+        .context("Failed to read Y4M header")?
+        == 0
+    {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(anyhow!("Truncated Y4M header"));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .context("Invalid Y4M header")
+}
+
+/// Fill `buf`, returning `false` if the reader is already at the end.
+fn read_exact_or_eof<R>(reader: &mut R, buf: &mut [u8]) -> anyhow::Result<bool>
+where
+    R: Read,
+{
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(anyhow!("Truncated frame")),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e).context("Failed to read frame"),
+        }
+    }
+    Ok(true)
+}
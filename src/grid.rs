@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::pixfmt::{yuv_to_rgb, GRAY_CHROMA};
+use crate::Brightness;
+
+/// A character, the average brightness of the pixels it stands for and,
+/// unless the grid is grayscale, their average color.
+#[derive(Debug, Clone, Copy)]
+pub struct AsciiCell {
+    pub c: char,
+    pub brightness: Brightness,
+    pub rgb: Option<(u8, u8, u8)>,
+}
+
+impl AsciiCell {
+    /// The cell's color, or its gray level if it has none.
+    #[must_use]
+    pub const fn color(&self) -> (u8, u8, u8) {
+        match self.rgb {
+            Some(rgb) => rgb,
+            None => yuv_to_rgb(self.brightness.value(), GRAY_CHROMA, GRAY_CHROMA),
+        }
+    }
+}
+
+/// The characters standing for each `cell_width` x `cell_height` cell of a
+/// frame, row by row. Cells in the last row and column may be clipped by
+/// the frame.
+#[derive(Debug, Clone, Default)]
+pub struct AsciiGrid {
+    cols: u32,
+    rows: u32,
+    cell_width: u32,
+    cell_height: u32,
+    cells: Vec<AsciiCell>,
+}
+
+impl AsciiGrid {
+    pub(crate) fn new(
+        cols: u32,
+        rows: u32,
+        cell_width: u32,
+        cell_height: u32,
+        cells: Vec<AsciiCell>,
+    ) -> Self {
+        debug_assert!(
+            cells.len() == (cols * rows) as usize,
+            "cells.len()={} cols={cols} rows={rows}",
+            cells.len()
+        );
+        Self {
+            cols,
+            rows,
+            cell_width,
+            cell_height,
+            cells,
+        }
+    }
+
+    #[must_use]
+    pub const fn cols(&self) -> u32 {
+        self.cols
+    }
+
+    #[must_use]
+    pub const fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// The size in pixels of the frame area each cell stands for.
+    #[must_use]
+    pub const fn cell_size(&self) -> (u32, u32) {
+        (self.cell_width, self.cell_height)
+    }
+
+    /// Whether the cells carry no color.
+    #[must_use]
+    pub fn is_grayscale(&self) -> bool {
+        self.cells.iter().all(|cell| cell.rgb.is_none())
+    }
+
+    #[must_use]
+    pub fn get(&self, col: u32, row: u32) -> Option<&AsciiCell> {
+        (col < self.cols && row < self.rows).then(|| &self.cells[(row * self.cols + col) as usize])
+    }
+
+    /// The rows of cells, top to bottom.
+    pub fn iter_rows(&self) -> impl Iterator<Item = &[AsciiCell]> {
+        // chunks panics on 0
+        self.cells.chunks(self.cols.max(1) as usize)
+    }
+}
+
+/// One line of characters per row, without color.
+impl fmt::Display for AsciiGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.iter_rows() {
+            let line = row.iter().map(|cell| cell.c).collect::<String>();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::{Frame, PixelFormat};
+
+/// A still image format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary PPM (P6).
+    Ppm,
+    /// Binary PGM (P5), storing only the brightness.
+    Pgm,
+}
+
+impl ImageFormat {
+    /// Pick the format from the extension of `path`, .png, .ppm or .pgm.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "png" => Some(Self::Png),
+            "ppm" => Some(Self::Ppm),
+            "pgm" => Some(Self::Pgm),
+            _ => None,
+        }
+    }
+}
+
+/// Write `frame` to `writer` as a `format` image.
+pub fn encode<W>(frame: &Frame<'_>, format: ImageFormat, mut writer: W) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let (width, height) = (frame.width(), frame.height());
+    match format {
+        ImageFormat::Png => {
+            let mut encoder = png::Encoder::new(writer, width, height);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header()?;
+            writer.write_image_data(&rgb_bytes(frame))?;
+            writer.finish()?;
+        }
+        ImageFormat::Ppm => {
+            write!(writer, "P6\n{width} {height}\n255\n")?;
+            writer.write_all(&rgb_bytes(frame))?;
+            writer.flush()?;
+        }
+        ImageFormat::Pgm => {
+            write!(writer, "P5\n{width} {height}\n255\n")?;
+            let luma = (0..height)
+                .flat_map(|y| (0..width).map(move |x| frame.get_brightness(x, y).value()))
+                .collect::<Vec<_>>();
+            writer.write_all(&luma)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn rgb_bytes(frame: &Frame<'_>) -> Vec<u8> {
+    let (width, height) = (frame.width(), frame.height());
+    if frame.format() == PixelFormat::Rgb24 {
+        return frame.to_bytes();
+    }
+    let mut buf = vec![0; PixelFormat::Rgb24.frame_size(width, height)];
+    frame.convert_into(&mut Frame::with_format(
+        &mut buf,
+        width,
+        height,
+        PixelFormat::Rgb24,
+    ));
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3x2 frame: black, white and red, then red, white and black.
+    const RGB: [u8; 18] = [
+        0, 0, 0, 255, 255, 255, 255, 0, 0, //
+        255, 0, 0, 255, 255, 255, 0, 0, 0,
+    ];
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut buf = RGB;
+        let frame = Frame::with_format(&mut buf, 3, 2, PixelFormat::Rgb24);
+        let mut out = Vec::new();
+        encode(&frame, format, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn formats_from_extensions() {
+        let format = |path: &str| ImageFormat::from_path(Path::new(path));
+        assert_eq!(format("a.png"), Some(ImageFormat::Png));
+        assert_eq!(format("a.ppm"), Some(ImageFormat::Ppm));
+        assert_eq!(format("a.pgm"), Some(ImageFormat::Pgm));
+        assert_eq!(format("a.gif"), None);
+        assert_eq!(format("png"), None);
+    }
+
+    #[test]
+    fn ppm_header_and_pixels() {
+        let ppm = encoded(ImageFormat::Ppm);
+        let (header, pixels) = ppm.split_at(11);
+        assert_eq!(header, b"P6\n3 2\n255\n");
+        assert_eq!(pixels, RGB);
+    }
+
+    #[test]
+    fn pgm_header_and_luma() {
+        let pgm = encoded(ImageFormat::Pgm);
+        let (header, luma) = pgm.split_at(11);
+        assert_eq!(header, b"P5\n3 2\n255\n");
+        // Studio-range luma, from 16 for black to 235 for white
+        assert_eq!(luma, [16, 235, 82, 82, 235, 16]);
+    }
+
+    #[test]
+    fn png_header_and_pixels() {
+        let png = encoded(ImageFormat::Png);
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR: width, height, bit depth and RGB color type
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..26], [0, 0, 0, 3, 0, 0, 0, 2, 8, 2]);
+
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, RGB);
+    }
+}
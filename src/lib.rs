@@ -15,7 +15,8 @@
 
 use std::cmp;
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
 use std::ops::Index;
 use std::path::{Path, PathBuf};
 
@@ -23,13 +24,49 @@ use anyhow::{anyhow, Context};
 use itertools::Itertools;
 use rayon::prelude::*;
 use rusttype::{point, Font, Scale, ScaledGlyph};
-use v4l::{
-    buffer::Type,
-    format::fourcc::FourCC,
-    io::traits::{CaptureStream, OutputStream},
-    prelude::*,
-    video::{output::Parameters as OutputParameters, Capture, Output},
-};
+use v4l::Fraction;
+
+mod blocks;
+mod braille;
+mod convert;
+mod device;
+mod dither;
+mod edges;
+mod export;
+mod exposure;
+mod file;
+mod grid;
+mod image;
+mod mjpeg;
+mod pacer;
+mod pattern;
+mod pixfmt;
+mod record;
+mod shape;
+mod stabilize;
+mod text;
+mod tone;
+
+pub use blocks::BlockCharset;
+use blocks::{HALF_BLOCKS, QUADRANTS, SHADES};
+pub use device::{DeviceSink, DeviceSource};
+pub use dither::Dithering;
+use edges::{EdgeDetector, EDGE_CHARS};
+pub use export::{DocumentFormat, DocumentSink, Exporter};
+pub use exposure::Exposure;
+use exposure::Histogram;
+pub use file::{FileSink, FileSource};
+pub use grid::{AsciiCell, AsciiGrid};
+pub use image::ImageFormat;
+pub use pattern::{Pattern, PatternSource};
+use pixfmt::Pixels;
+pub use pixfmt::{CaptureFormat, PixelFormat};
+pub use record::{AnimationFormat, Encoding, Recorder};
+pub use shape::GlyphMatching;
+use shape::ShapeMatcher;
+use stabilize::Stabilizer;
+pub use text::{TextColor, TextSink};
+pub use tone::ToneMap;
 
 // $@B%8&WM#*oahkbdpqwmZO0QLCJUYXzcvunxrjft/\|()1{}[]?-_+~<>i!lI;:,"^`'.
 const ASCII_MAP_NBITS: u32 = 6;
@@ -43,6 +80,9 @@ const ASCII_MAP_64: [char; 2_usize.pow(ASCII_MAP_NBITS)] = [
 const DEFAULT_FONT: &[u8] = include_bytes!("../font/FiraCode-VF.ttf");
 const DEFAULT_FONT_SCALE: u32 = 10;
 
+const DEFAULT_INPUT_FPS: u32 = 30;
+const DEFAULT_PATTERN_SIZE: (u32, u32) = (1280, 720);
+
 // TODO: Set this based on frame size
 const NSUBFRAME_SPLITS: u32 = 4;
 
@@ -100,6 +140,45 @@ impl RenderedGlyph {
 
         Self(pts)
     }
+
+    /// Fill a `width` x `height` cell with the block element `c`, if it is
+    /// one.
+    #[must_use]
+    pub fn block(c: char, width: u32, height: u32) -> Option<Self> {
+        let coverage = blocks::coverage(c, width, height)?;
+        Some(Self::from_coverage(width, coverage))
+    }
+
+    /// Draw the dots of the braille pattern `c` in a `width` x `height`
+    /// cell, if it is one.
+    #[must_use]
+    pub fn braille(c: char, width: u32, height: u32) -> Option<Self> {
+        let coverage = braille::coverage(c, width, height)?;
+        Some(Self::from_coverage(width, coverage))
+    }
+
+    /// The glyph covering each pixel of a cell `width` wide by `coverage`,
+    /// row by row.
+    fn from_coverage(width: u32, coverage: Vec<u8>) -> Self {
+        let pts = (0..)
+            .cartesian_product(0..width)
+            .zip(coverage)
+            .map(|((y, x), b)| (x, y, Brightness(b)))
+            .collect();
+        Self(pts)
+    }
+
+    /// Render `c` in `font` at `scale`, filling the cell exactly if it is a
+    /// block element, and drawing braille patterns the font lacks.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn render(font: &Font<'_>, c: char, scale: Scale) -> Self {
+        let (width, height) = (scale.x as u32, scale.y as u32);
+        let glyph = font.glyph(c);
+        let missing = glyph.id().0 == 0;
+        Self::block(c, width, height)
+            .or_else(|| missing.then(|| Self::braille(c, width, height)).flatten())
+            .unwrap_or_else(|| Self::new(glyph.scaled(scale)))
+    }
 }
 
 pub struct GlyphMapBuilder<'chars> {
@@ -171,6 +250,8 @@ impl<'chars> GlyphMapBuilder<'chars> {
 #[derive(Debug, Clone)]
 pub struct GlyphMap<'font> {
     font: Font<'font>,
+    /// The font's family name, if it has one.
+    family: Option<String>,
     glyphs: HashMap<char, RenderedGlyph>,
     width: u32,
     height: u32,
@@ -182,21 +263,95 @@ impl<'font> GlyphMap<'font> {
     pub fn new(font: Font<'font>, scale: Scale, chars: &[char]) -> Self {
         let glyphs = chars
             .iter()
-            .map(|&c| (c, RenderedGlyph::new(font.glyph(c).scaled(scale))))
+            .map(|&c| (c, RenderedGlyph::render(&font, c, scale)))
             .collect();
+        let family = match &font {
+            Font::Ref(face) => family_name(face),
+            Font::Owned(face) => ttf_parser::Face::from_slice(face.as_slice(), 0)
+                .ok()
+                .and_then(|face| family_name(&face)),
+        };
         Self {
             font,
+            family,
             glyphs,
             width: scale.x as u32,
             height: scale.y as u32,
         }
     }
 
+    /// The font's family name, if it has one.
+    #[must_use]
+    pub fn family(&self) -> Option<&str> {
+        self.family.as_deref()
+    }
+
     #[must_use]
     pub fn get(&self, c: &char) -> Option<&RenderedGlyph> {
         self.glyphs.get(c)
     }
 
+    /// The fraction of its cell the glyph of `c` covers with ink, if it was
+    /// rendered.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn coverage(&self, c: char) -> Option<f32> {
+        let glyph = self.glyphs.get(&c)?;
+        let ink = glyph
+            .0
+            .iter()
+            .map(|(_, _, b)| u64::from(b.value()))
+            .sum::<u64>();
+        let area = u64::from(self.width * self.height).max(1) * u64::from(u8::MAX);
+        Some(ink as f32 / area as f32)
+    }
+
+    /// The rendered ones of `chars`, from the most ink to the least, which is
+    /// the order of a charset from dark to bright cells.
+    #[must_use]
+    pub fn sort_by_coverage(&self, chars: &[char]) -> Vec<char> {
+        chars
+            .iter()
+            .filter_map(|&c| Some((c, self.coverage(c)?)))
+            .sorted_by(|(_, coverage1), (_, coverage2)| coverage2.total_cmp(coverage1))
+            .map(|(c, _)| c)
+            .collect()
+    }
+
+    /// Pick `n` of `chars`, sorted by coverage, whose coverage is as evenly
+    /// spaced as possible from the most to the least ink among them.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn resample_by_coverage(&self, chars: &[char], n: usize) -> Vec<char> {
+        let mut candidates = self
+            .sort_by_coverage(chars)
+            .into_iter()
+            .map(|c| (c, self.coverage(c).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        if n >= candidates.len() {
+            return candidates.into_iter().map(|(c, _)| c).collect();
+        }
+        let (Some(&(_, most)), Some(&(_, least))) = (candidates.first(), candidates.last()) else {
+            return vec![];
+        };
+        let step = (most - least) / n.saturating_sub(1).max(1) as f32;
+        let mut picked = (0..n)
+            .filter_map(|idx| {
+                let target = most - idx as f32 * step;
+                let (pos, _) =
+                    candidates
+                        .iter()
+                        .enumerate()
+                        .min_by(|(_, (_, c1)), (_, (_, c2))| {
+                            (c1 - target).abs().total_cmp(&(c2 - target).abs())
+                        })?;
+                Some(candidates.remove(pos))
+            })
+            .collect::<Vec<_>>();
+        picked.sort_by(|(_, coverage1), (_, coverage2)| coverage2.total_cmp(coverage1));
+        picked.into_iter().map(|(c, _)| c).collect()
+    }
+
     #[allow(
         clippy::cast_possible_wrap,
         clippy::cast_precision_loss,
@@ -212,7 +367,23 @@ impl<'font> GlyphMap<'font> {
             y: self.height as f32,
         };
         for (c, g) in &mut self.glyphs {
-            *g = RenderedGlyph::new(self.font.glyph(*c).scaled(scale));
+            *g = RenderedGlyph::render(&self.font, *c, scale);
+        }
+        self
+    }
+
+    /// Also render `chars`, keeping the glyphs already rendered.
+    #[allow(clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn add_chars(mut self, chars: &[char]) -> Self {
+        let scale = Scale {
+            x: self.width as f32,
+            y: self.height as f32,
+        };
+        for &c in chars {
+            self.glyphs
+                .entry(c)
+                .or_insert_with(|| RenderedGlyph::render(&self.font, c, scale));
         }
         self
     }
@@ -224,33 +395,81 @@ impl<'font> GlyphMap<'font> {
             x: self.width as f32,
             y: self.height as f32,
         };
-        Self::new(self.font, scale, chars)
+        let glyphs = chars
+            .iter()
+            .map(|&c| (c, RenderedGlyph::render(&self.font, c, scale)))
+            .collect();
+        Self { glyphs, ..self }
     }
 }
 
+/// Prefer the typographic family, which groups more than four styles, e.g.
+/// all the weights of a variable font.
+fn family_name(face: &ttf_parser::Face<'_>) -> Option<String> {
+    [
+        ttf_parser::name_id::TYPOGRAPHIC_FAMILY,
+        ttf_parser::name_id::FAMILY,
+    ]
+    .into_iter()
+    .find_map(|name_id| {
+        face.names()
+            .into_iter()
+            .filter(|name| name.name_id == name_id)
+            .find_map(|name| name.to_string())
+    })
+}
+
+/// A charset, from the character for the darkest cells to the one for the
+/// brightest, each standing for an equal share of the brightness range.
+/// Charsets of patterns instead have a character for each combination of
+/// dark sub-cells, indexed by the bits of the dark ones in reading order.
 #[derive(Debug, Clone)]
 pub struct AsciiMap {
     map: Vec<char>,
-    nbits: u32,
+    /// The columns and rows of sub-cells of a pattern charset.
+    subcells: Option<(u32, u32)>,
 }
 
 impl AsciiMap {
-    #[allow(
-        clippy::cast_possible_truncation,
-        clippy::cast_precision_loss,
-        clippy::cast_sign_loss
-    )]
+    /// Fails if `map` is empty, has more than one character per brightness
+    /// level, or has any character twice.
+    pub fn new(map: Vec<char>) -> anyhow::Result<Self> {
+        if map.is_empty() {
+            return Err(anyhow!("The charset is empty"));
+        }
+        if map.len() > 256 {
+            return Err(anyhow!(
+                "The charset has {} characters, expected at most 256",
+                map.len()
+            ));
+        }
+        if let Some(c) = map.iter().duplicates().next() {
+            return Err(anyhow!("The charset has {c:?} more than once"));
+        }
+        Ok(Self {
+            map,
+            subcells: None,
+        })
+    }
+
     #[must_use]
-    pub fn new(map: Vec<char>) -> Self {
-        let nbits = (map.len() as f32).log2() as u32;
-        debug_assert!(
-            2usize.pow(nbits) == map.len(),
-            "len={}, nbits={}",
-            map.len(),
-            nbits
-        );
-        debug_assert!(nbits <= u8::BITS, "nbits={nbits}");
-        Self { map, nbits }
+    pub fn from_blocks(blocks: BlockCharset) -> Self {
+        let (map, subcells) = match blocks {
+            BlockCharset::Shades => (SHADES.to_vec(), None),
+            BlockCharset::HalfBlocks => (HALF_BLOCKS.to_vec(), Some((1, 2))),
+            BlockCharset::Quadrants => (QUADRANTS.to_vec(), Some((2, 2))),
+        };
+        Self { map, subcells }
+    }
+
+    /// Braille patterns, picked by the brightness of each 2x4 dot of a cell.
+    /// Fonts without braille glyphs get their dots drawn instead.
+    #[must_use]
+    pub fn braille() -> Self {
+        Self {
+            map: braille::patterns(),
+            subcells: Some((2, 4)),
+        }
     }
 
     #[must_use]
@@ -258,6 +477,14 @@ impl AsciiMap {
         &self.map
     }
 
+    /// The columns and rows of sub-cells, if the charset is of patterns.
+    #[must_use]
+    pub const fn subcells(&self) -> Option<(u32, u32)> {
+        self.subcells
+    }
+
+    /// Swap the characters for dark and bright cells, which for patterns
+    /// swaps dark and bright sub-cells.
     pub fn invert(&mut self) {
         self.map.reverse();
     }
@@ -266,10 +493,8 @@ impl AsciiMap {
 impl Index<Brightness> for AsciiMap {
     type Output = char;
 
-    #[must_use]
     fn index(&self, idx: Brightness) -> &Self::Output {
-        let idx = idx.0 >> (u8::BITS - self.nbits);
-        &self.map[idx as usize]
+        &self.map[usize::from(idx.0) * self.map.len() / 256]
     }
 }
 
@@ -277,6 +502,11 @@ impl Index<Brightness> for AsciiMap {
 pub struct Brightness(u8);
 
 impl Brightness {
+    #[must_use]
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+
     #[must_use]
     pub fn as_ascii(self, map: &AsciiMap) -> char {
         map[self]
@@ -285,172 +515,227 @@ impl Brightness {
 
 impl From<f32> for Brightness {
     #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    #[must_use]
     fn from(b: f32) -> Self {
         Self((b * 255.0).clamp(0.0, 255.0) as u8)
     }
 }
 
 #[derive(Debug)]
-struct Yuyv<'pix> {
-    buf: &'pix mut [u8],
-    width: u32,
-    height: u32,
+pub struct Frame<'pix> {
+    pixels: Pixels<'pix>,
 }
 
-impl<'pix> Yuyv<'pix> {
+impl<'pix> Frame<'pix> {
     #[must_use]
     pub fn new(buf: &'pix mut [u8], width: u32, height: u32) -> Self {
-        Self { buf, width, height }
+        Self::with_format(buf, width, height, PixelFormat::Yuyv)
+    }
+
+    #[must_use]
+    pub fn with_format(buf: &'pix mut [u8], width: u32, height: u32, format: PixelFormat) -> Self {
+        Self {
+            pixels: Pixels::new(buf, width, height, format),
+        }
     }
 
     #[must_use]
-    pub fn splitn(&mut self, n: u32) -> Vec<Yuyv<'_>> {
-        debug_assert!(n > 0);
-        debug_assert!(self.height % (2_u32.pow(n)) == 0);
-        let len = self.buf.len() >> n;
-        let height = self.height >> n;
-        self.buf
-            .chunks_exact_mut(len)
-            .map(|sub| Yuyv::new(sub, self.width, height))
+    pub fn splitn(&mut self, n: u32) -> Vec<Frame<'_>> {
+        self.pixels
+            .splitn(n)
+            .into_iter()
+            .map(|pixels| Frame { pixels })
             .collect()
     }
 
+    pub fn as_grayscale(&mut self) {
+        self.pixels.as_grayscale();
+    }
+
     #[must_use]
-    pub const fn iter_avg(&self, width: u32, height: u32) -> IterAvg<'_> {
-        IterAvg::new(self, width, height)
+    pub fn get_brightness(&self, x: u32, y: u32) -> Brightness {
+        self.pixels.get_brightness(x, y)
     }
 
     #[must_use]
-    pub const fn get_brightness(&self, x: u32, y: u32) -> Brightness {
-        Brightness(self.buf[self.xy_to_idx(x, y)])
+    pub fn get_rgb(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        self.pixels.get_rgb(x, y)
     }
 
-    pub fn set_brightness<B>(&mut self, x: u32, y: u32, b: B)
+    pub fn set_rgb(&mut self, x: u32, y: u32, rgb: (u8, u8, u8)) {
+        self.pixels.set_rgb(x, y, rgb);
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pixels.planes().flatten().copied().collect()
+    }
+
+    /// Scale and convert into `dst`, which may have a different size and
+    /// `PixelFormat`.
+    pub fn convert_into(&self, dst: &mut Frame<'_>) {
+        convert::convert(&self.pixels, &mut dst.pixels);
+    }
+
+    /// Encode as an image in `format`.
+    pub fn write_image<W>(&self, format: ImageFormat, writer: W) -> anyhow::Result<()>
     where
-        B: Into<Brightness>,
+        W: Write,
     {
-        let idx = self.xy_to_idx(x, y);
-        self.buf[idx] = b.into().0;
+        image::encode(self, format, writer)
     }
 
-    pub fn as_grayscale(&mut self) {
-        self.buf.fill(127);
+    /// Save as an image in the format given by the extension of `path`.
+    pub fn save_image<P>(&self, path: P) -> anyhow::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).with_context(|| {
+            format!(
+                "Unknown image format {}, expected a .png, .ppm or .pgm file",
+                path.display()
+            )
+        })?;
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        self.write_image(format, BufWriter::new(file))
+            .with_context(|| format!("Failed to write image {}", path.display()))
+    }
+
+    pub fn copy_to_slice(&self, buf: &mut [u8]) {
+        let mut rest = buf;
+        for plane in self.pixels.planes() {
+            let (head, tail) = rest.split_at_mut(plane.len());
+            head.copy_from_slice(plane);
+            rest = tail;
+        }
     }
 
-    // https://egeeks.github.io/kernal/media/V4L2-PIX-FMT-YUYV.html
-    // 0       1       2       3
-    // Y1 U1/2 Y2 V1/2 Y3 U3/4 Y4 V3/4      0
-    // Y5 U5/6 Y6 V5/6 Y7 U7/8 Y8 V7/8      1
     #[must_use]
-    const fn xy_to_idx(&self, x: u32, y: u32) -> usize {
-        (2 * (y * self.width + x)) as usize
+    pub const fn format(&self) -> PixelFormat {
+        self.pixels.format()
     }
-}
 
-#[derive(Debug)]
-struct IterAvg<'pix> {
-    pixels: &'pix Yuyv<'pix>,
-    width: u32,
-    height: u32,
-    x: u32,
-    y: u32,
-    done: bool,
-}
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.pixels.width()
+    }
 
-impl<'pix> IterAvg<'pix> {
     #[must_use]
-    const fn new(pixels: &'pix Yuyv<'pix>, width: u32, height: u32) -> Self {
-        Self {
-            pixels,
-            width,
-            height,
-            x: 0,
-            y: 0,
-            done: false,
-        }
+    pub const fn height(&self) -> u32 {
+        self.pixels.height()
     }
 }
 
-impl Iterator for IterAvg<'_> {
-    type Item = (u32, u32, Brightness);
+/// Processes the frames of a stream in place, possibly keeping state from
+/// one frame to the next.
+pub trait FrameFilter {
+    fn process(&mut self, frame: &mut Frame<'_>);
+}
 
-    #[allow(clippy::cast_possible_truncation)]
-    #[must_use]
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.done {
-            None
-        } else {
-            // Average the brightness of the next group_sz pixels in the x and
-            // y directions.
-            let next_x = cmp::min(self.x + self.width, self.pixels.width);
-            let next_y = cmp::min(self.y + self.height, self.pixels.height);
-            let npix = (next_x - self.x) * (next_y - self.y);
-            let avg = (self.x..next_x)
-                .cartesian_product(self.y..next_y)
-                .map(|(x, y)| u32::from(self.pixels.get_brightness(x, y).0))
-                .sum::<u32>()
-                / npix;
-            debug_assert!(avg <= u8::MAX.into(), "avg={avg}");
-            let ret = (self.x, self.y, Brightness(avg as u8));
-
-            if next_x < self.pixels.width {
-                self.x = next_x;
-            } else if next_y < self.pixels.height {
-                self.x = 0;
-                self.y = next_y;
-            } else {
-                self.done = true;
-            }
-            Some(ret)
-        }
-    }
+/// A stream of frames to be processed.
+pub trait FrameSource {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    /// The `PixelFormat` of the frames read by `read_frame`.
+    fn format(&self) -> PixelFormat;
+    /// The time between frames in seconds.
+    fn interval(&self) -> Fraction;
+    /// Read the next frame into `buf`, which holds exactly one frame in
+    /// `format`. Returns `false` once there are no more frames.
+    fn read_frame(&mut self, buf: &mut [u8]) -> anyhow::Result<bool>;
 }
 
-#[derive(Debug)]
-pub struct Frame<'pix> {
-    pixels: Yuyv<'pix>,
+/// A destination for processed frames.
+pub trait FrameSink {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn format(&self) -> PixelFormat;
+    /// Convert `frame` to the sink's size and format and output it.
+    fn write_frame(&mut self, frame: &Frame<'_>) -> anyhow::Result<()>;
 }
 
-impl<'pix> Frame<'pix> {
-    #[must_use]
-    pub fn new(buf: &'pix mut [u8], width: u32, height: u32) -> Self {
-        Self {
-            pixels: Yuyv::new(buf, width, height),
-        }
+impl<S> FrameSource for Box<S>
+where
+    S: FrameSource + ?Sized,
+{
+    fn width(&self) -> u32 {
+        (**self).width()
     }
 
-    #[must_use]
-    pub fn splitn(&mut self, n: u32) -> Vec<Frame<'_>> {
-        self.pixels
-            .splitn(n)
-            .into_iter()
-            .map(|pixels| Frame { pixels })
-            .collect()
+    fn height(&self) -> u32 {
+        (**self).height()
     }
 
-    pub fn as_grayscale(&mut self) {
-        self.pixels.as_grayscale();
+    fn format(&self) -> PixelFormat {
+        (**self).format()
     }
 
-    #[must_use]
-    pub const fn as_bytes(&self) -> &[u8] {
-        self.pixels.buf
+    fn interval(&self) -> Fraction {
+        (**self).interval()
     }
 
-    #[must_use]
-    pub const fn width(&self) -> u32 {
-        self.pixels.width
+    fn read_frame(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        (**self).read_frame(buf)
     }
+}
+
+impl<K> FrameSink for Box<K>
+where
+    K: FrameSink + ?Sized,
+{
+    fn width(&self) -> u32 {
+        (**self).width()
+    }
+
+    fn height(&self) -> u32 {
+        (**self).height()
+    }
+
+    fn format(&self) -> PixelFormat {
+        (**self).format()
+    }
+
+    fn write_frame(&mut self, frame: &Frame<'_>) -> anyhow::Result<()> {
+        (**self).write_frame(frame)
+    }
+}
+
+/// A `FrameSink` that discards every frame.
+#[derive(Debug, Clone, Copy)]
+pub struct NullSink {
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+}
 
+impl NullSink {
     #[must_use]
-    pub const fn height(&self) -> u32 {
-        self.pixels.height
+    pub const fn new(width: u32, height: u32, format: PixelFormat) -> Self {
+        Self {
+            width,
+            height,
+            format,
+        }
     }
 }
 
-pub trait FrameFilter {
-    fn process(&self, frame: &mut Frame<'_>);
+impl FrameSink for NullSink {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn write_frame(&mut self, _frame: &Frame<'_>) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -458,6 +743,9 @@ pub enum AsciiMode {
     Grayscale,
     Color,
     Invert,
+    /// Color, drawing strong edges with `|`, `/`, `-`, `\` and `_` along
+    /// their direction.
+    Edges,
 }
 
 impl AsciiMode {
@@ -466,7 +754,8 @@ impl AsciiMode {
         match self {
             Self::Grayscale => Self::Color,
             Self::Color => Self::Invert,
-            Self::Invert => Self::Grayscale,
+            Self::Invert => Self::Edges,
+            Self::Edges => Self::Grayscale,
         }
     }
 }
@@ -476,6 +765,16 @@ pub struct AsciiFilter<'font> {
     ascii_map: AsciiMap,
     glyphs: GlyphMap<'font>,
     mode: AsciiMode,
+    matching: GlyphMatching,
+    /// The glyph shapes of the charset when matching by shape, rebuilt
+    /// whenever the charset or its glyphs change.
+    matcher: Option<ShapeMatcher>,
+    dithering: Dithering,
+    tone: ToneMap,
+    exposure: Exposure,
+    histogram: Histogram,
+    smoothing: f32,
+    stabilizer: Stabilizer,
 }
 
 impl<'font> AsciiFilter<'font> {
@@ -484,19 +783,75 @@ impl<'font> AsciiFilter<'font> {
         if matches!(mode, AsciiMode::Invert) {
             ascii_map.invert();
         }
+        let glyphs = if matches!(mode, AsciiMode::Edges) {
+            glyphs.add_chars(&EDGE_CHARS)
+        } else {
+            glyphs
+        };
         Self {
             ascii_map,
             glyphs,
             mode,
+            matching: GlyphMatching::default(),
+            matcher: None,
+            dithering: Dithering::default(),
+            tone: ToneMap::new(),
+            exposure: Exposure::default(),
+            histogram: Histogram::default(),
+            smoothing: 0.0,
+            stabilizer: Stabilizer::default(),
         }
     }
 
+    #[must_use]
+    pub fn with_matching(mut self, matching: GlyphMatching) -> Self {
+        self.matching = matching;
+        self.update_matcher();
+        self
+    }
+
+    /// Dither characters picked by brightness.
+    #[must_use]
+    pub const fn with_dithering(mut self, dithering: Dithering) -> Self {
+        self.dithering = dithering;
+        self
+    }
+
+    /// Adjust the brightness of cells before picking their characters.
+    #[must_use]
+    pub const fn with_tone(mut self, tone: ToneMap) -> Self {
+        self.tone = tone;
+        self
+    }
+
+    /// Adapt the brightness of cells to each frame, before adjusting their
+    /// tone. The histogram starts afresh from the next frame.
+    #[must_use]
+    pub fn with_exposure(mut self, exposure: Exposure) -> Self {
+        self.exposure = exposure;
+        self.histogram = Histogram::default();
+        self
+    }
+
+    /// Average the brightness of cells over frames, weighting the previous
+    /// average by `smoothing`, from 0 to turn it off to just below 1, and
+    /// hold each cell's character until its brightness clearly changes.
+    #[must_use]
+    pub const fn with_smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 0.99);
+        self
+    }
+
     #[must_use]
     pub fn cycle_mode(mut self) -> Self {
         let old_mode = self.mode;
         self.mode = self.mode.next();
         if matches!(old_mode, AsciiMode::Invert) || matches!(self.mode, AsciiMode::Invert) {
             self.ascii_map.invert();
+            self.update_matcher();
+        }
+        if matches!(self.mode, AsciiMode::Edges) {
+            self.glyphs = self.glyphs.add_chars(&EDGE_CHARS);
         }
         self
     }
@@ -506,9 +861,35 @@ impl<'font> AsciiFilter<'font> {
         self.mode
     }
 
+    #[must_use]
+    pub const fn matching(&self) -> GlyphMatching {
+        self.matching
+    }
+
+    #[must_use]
+    pub const fn dithering(&self) -> Dithering {
+        self.dithering
+    }
+
+    #[must_use]
+    pub const fn tone(&self) -> &ToneMap {
+        &self.tone
+    }
+
+    #[must_use]
+    pub const fn exposure(&self) -> Exposure {
+        self.exposure
+    }
+
+    #[must_use]
+    pub const fn smoothing(&self) -> f32 {
+        self.smoothing
+    }
+
     #[must_use]
     pub fn resize(mut self, inc: i32) -> Self {
         self.glyphs = self.glyphs.resize(inc);
+        self.update_matcher();
         self
     }
 
@@ -518,124 +899,551 @@ impl<'font> AsciiFilter<'font> {
     }
 
     #[must_use]
-    pub fn set_charset(mut self, chars: Vec<char>) -> Self {
-        self.glyphs = self.glyphs.set_charset(&chars);
-        self.ascii_map = AsciiMap::new(chars);
+    pub fn font_family(&self) -> Option<&str> {
+        self.glyphs.family()
+    }
+
+    #[must_use]
+    pub fn set_charset(mut self, mut ascii_map: AsciiMap) -> Self {
+        if matches!(self.mode, AsciiMode::Invert) {
+            ascii_map.invert();
+        }
+        self.glyphs = self.glyphs.set_charset(ascii_map.chars());
+        if matches!(self.mode, AsciiMode::Edges) {
+            self.glyphs = self.glyphs.add_chars(&EDGE_CHARS);
+        }
+        self.ascii_map = ascii_map;
+        self.update_matcher();
         self
     }
-}
 
-impl FrameFilter for AsciiFilter<'_> {
-    fn process(&self, frame: &mut Frame<'_>) {
-        let mut buf = frame.as_bytes().to_vec();
-        let mut old_frame = Frame::new(&mut buf, frame.width(), frame.height());
+    /// The charset, from the character for the darkest cells to the one for
+    /// the brightest, before any inversion.
+    #[must_use]
+    pub fn charset(&self) -> Vec<char> {
+        let mut chars = self.ascii_map.chars().to_vec();
+        if matches!(self.mode, AsciiMode::Invert) {
+            chars.reverse();
+        }
+        chars
+    }
+
+    /// Order the charset by how much ink each character's glyph has, keeping
+    /// only `n` characters with evenly spaced ink if given, so that any font
+    /// gives a smooth ramp.
+    #[must_use]
+    pub fn sort_charset(mut self, n: Option<usize>) -> Self {
+        // Patterns are indexed by sub-cells, not ordered by ink
+        if self.ascii_map.subcells().is_some() {
+            return self;
+        }
+        let chars = self.ascii_map.chars();
+        let mut map = match n {
+            Some(n) => self.glyphs.resample_by_coverage(chars, n.max(1)),
+            None => self.glyphs.sort_by_coverage(chars),
+        };
+        if matches!(self.mode, AsciiMode::Invert) {
+            map.reverse();
+        }
+        // Every character of the charset has a glyph
+        self.ascii_map = AsciiMap {
+            map,
+            subcells: None,
+        };
+        self.update_matcher();
+        self
+    }
+
+    /// Build the glyph shapes of the charset if matching by shape, as
+    /// pattern charsets aren't.
+    fn update_matcher(&mut self) {
+        let shape = matches!(self.matching, GlyphMatching::Shape);
+        self.matcher = (shape && self.ascii_map.subcells().is_none())
+            .then(|| ShapeMatcher::new(&self.ascii_map, &self.glyphs));
+    }
+
+    /// Map each `cell_width` x `cell_height` cell of `frame` to a character,
+    /// with its average color unless in grayscale mode. Successive frames
+    /// are taken to be from the same stream.
+    #[must_use]
+    pub fn to_grid(&mut self, frame: &Frame<'_>, cell_width: u32, cell_height: u32) -> AsciiGrid {
+        let color = !matches!(self.mode, AsciiMode::Grayscale);
+        self.grid(frame, cell_width, cell_height, color)
+    }
+
+    fn grid(
+        &mut self,
+        frame: &Frame<'_>,
+        cell_width: u32,
+        cell_height: u32,
+        color: bool,
+    ) -> AsciiGrid {
+        let pixels = &frame.pixels;
+        let cols = pixels.width().div_ceil(cell_width);
+        let rows = pixels.height().div_ceil(cell_height);
+        let edges = matches!(self.mode, AsciiMode::Edges).then(|| EdgeDetector::new(pixels));
+        let edges = edges.as_ref();
+        let mut brightness = (0..rows)
+            .into_par_iter()
+            .flat_map_iter(|row| {
+                (0..cols).map(move |col| {
+                    pixels.avg_brightness(
+                        col * cell_width,
+                        row * cell_height,
+                        cell_width,
+                        cell_height,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        let subcells = self.ascii_map.subcells();
+        // Patterns are smoothed by sub-cell instead
+        if self.smoothing > 0.0 && subcells.is_none() {
+            self.stabilizer
+                .smooth(self.smoothing, cols, rows, &mut brightness);
+        }
+        let tone = match self.exposure {
+            Exposure::Fixed => (!self.tone.is_identity()).then(|| self.tone.lut()),
+            exposure => {
+                let tone = self.tone.lut();
+                let lut = self.histogram.update(exposure, &brightness);
+                Some(lut.map(|b| tone[usize::from(b)]))
+            }
+        };
+        if let Some(lut) = tone {
+            for b in &mut brightness {
+                b.0 = lut[usize::from(b.0)];
+            }
+        }
+        let nchars = self.ascii_map.chars().len();
+        let levels = match (subcells, self.matching, self.dithering) {
+            (Some(subcells), _, _) => Some(self.patterns(
+                frame,
+                (cols, rows),
+                (cell_width, cell_height),
+                subcells,
+                tone.as_ref(),
+            )),
+            (None, GlyphMatching::Shape, _) => None,
+            (None, GlyphMatching::Brightness, Dithering::None) => (self.smoothing > 0.0)
+                .then(|| self.stabilizer.levels(cols, rows, &brightness, nchars)),
+            (None, GlyphMatching::Brightness, dithering) => {
+                Some(dither::quantize(dithering, &brightness, cols, rows, nchars))
+            }
+        };
+        let levels = levels.as_deref();
+        let matcher = self.matcher.as_ref();
+        let tone = tone.as_ref();
+        let chars = self.ascii_map.chars();
+        let cells = brightness
+            .into_par_iter()
+            .enumerate()
+            .map(|(idx, brightness)| {
+                #[allow(clippy::cast_possible_truncation)]
+                let (col, row) = (idx as u32 % cols, idx as u32 / cols);
+                let (x, y) = (col * cell_width, row * cell_height);
+                let edge = edges.and_then(|edges| edges.edge(x, y, cell_width, cell_height));
+                let c = edge.unwrap_or_else(|| match (levels, matcher) {
+                    (Some(levels), _) => chars[levels[idx]],
+                    (None, Some(matcher)) => {
+                        matcher.best(pixels, (x, y), (cell_width, cell_height), tone)
+                    }
+                    (None, None) => brightness.as_ascii(&self.ascii_map),
+                });
+                AsciiCell {
+                    c,
+                    brightness,
+                    rgb: color.then(|| pixels.avg_rgb(x, y, cell_width, cell_height)),
+                }
+            })
+            .collect();
+        AsciiGrid::new(cols, rows, cell_width, cell_height, cells)
+    }
+
+    /// The pattern of each cell of a pattern charset, as the bits of its
+    /// dark sub-cells in reading order. Sub-cells are thresholded at
+    /// mid-gray, dithered and smoothed like cells are, whatever the glyph
+    /// matching.
+    #[allow(clippy::cast_possible_truncation)]
+    fn patterns(
+        &mut self,
+        frame: &Frame<'_>,
+        (cols, rows): (u32, u32),
+        (cell_width, cell_height): (u32, u32),
+        (sub_cols, sub_rows): (u32, u32),
+        tone: Option<&[u8; 256]>,
+    ) -> Vec<usize> {
+        let pixels = &frame.pixels;
+        let (width, height) = (cols * sub_cols, rows * sub_rows);
+        // The start of each sub-cell along a cell, and of the next cell
+        let bounds = |n: u32, size: u32| (0..=n).map(move |i| i * size / n);
+        let xs = bounds(sub_cols, cell_width).collect::<Vec<_>>();
+        let ys = bounds(sub_rows, cell_height).collect::<Vec<_>>();
+        let mut brightness = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let (xs, ys) = (&xs, &ys);
+                (0..width).map(move |x| {
+                    let (i, j) = ((x % sub_cols) as usize, (y % sub_rows) as usize);
+                    // Sub-cells past the edge of the frame take the last
+                    // pixels before it
+                    let left = (x / sub_cols * cell_width + xs[i]).min(pixels.width() - 1);
+                    let top = (y / sub_rows * cell_height + ys[j]).min(pixels.height() - 1);
+                    pixels.avg_brightness(
+                        left,
+                        top,
+                        (xs[i + 1] - xs[i]).max(1),
+                        (ys[j + 1] - ys[j]).max(1),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        if self.smoothing > 0.0 {
+            self.stabilizer
+                .smooth(self.smoothing, width, height, &mut brightness);
+        }
+        if let Some(lut) = tone {
+            for b in &mut brightness {
+                b.0 = lut[usize::from(b.0)];
+            }
+        }
+        let levels = match self.dithering {
+            Dithering::None if self.smoothing > 0.0 => {
+                self.stabilizer.levels(width, height, &brightness, 2)
+            }
+            dithering => dither::quantize(dithering, &brightness, width, height, 2),
+        };
+        (0..rows)
+            .cartesian_product(0..cols)
+            .map(|(row, col)| {
+                (0..sub_rows)
+                    .cartesian_product(0..sub_cols)
+                    .map(|(j, i)| (row * sub_rows + j) * width + col * sub_cols + i)
+                    .enumerate()
+                    .filter(|&(_, idx)| levels[idx as usize] == 0)
+                    .fold(0, |pattern, (bit, _)| pattern | 1 << bit)
+            })
+            .collect()
+    }
+
+    /// Draw the glyphs of `grid` over `frame`, one per cell. Glyphs set the
+    /// brightness of the pixels under them and keep their color, except in
+    /// grayscale mode.
+    pub fn rasterize(&self, grid: &AsciiGrid, frame: &mut Frame<'_>) {
         match self.mode {
             AsciiMode::Grayscale => frame.as_grayscale(),
-            AsciiMode::Color | AsciiMode::Invert => {}
-        }
-
-        let mut subframes = frame.splitn(NSUBFRAME_SPLITS);
-        let old_subframes = old_frame.splitn(NSUBFRAME_SPLITS);
-        subframes
-            .par_iter_mut()
-            .zip(old_subframes)
-            .for_each(|(subframe, old_subframe)| {
-                let width = old_subframe.width();
-                let height = old_subframe.height();
-                old_subframe
-                    .pixels
-                    .iter_avg(self.glyphs.width, self.glyphs.height)
-                    .flat_map(|(x, y, pix)| {
-                        self.glyphs
-                            .get(&pix.as_ascii(&self.ascii_map))
-                            .unwrap()
-                            .0
-                            .iter()
-                            .filter_map(move |(xoff, yoff, b)| {
-                                let x = x + xoff;
-                                let y = y + yoff;
-                                (x < width && y < height).then_some((x, y, b))
-                            })
-                    })
-                    .for_each(|(x, y, b)| subframe.pixels.set_brightness(x, y, *b));
+            AsciiMode::Color | AsciiMode::Invert | AsciiMode::Edges => {}
+        }
+
+        let (cell_width, cell_height) = grid.cell_size();
+        // Bands of two rows of cells are a whole number of chroma rows
+        frame
+            .pixels
+            .split_rows(2 * cell_height)
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(band, mut pixels)| {
+                #[allow(clippy::cast_possible_truncation)]
+                let rows = (2 * band as u32)..(2 * band as u32 + 2);
+                for (row, col) in rows.cartesian_product(0..grid.cols()) {
+                    let Some(glyph) = grid.get(col, row).and_then(|cell| self.glyphs.get(&cell.c))
+                    else {
+                        continue;
+                    };
+                    let (x, y) = (col * cell_width, (row % 2) * cell_height);
+                    for (xoff, yoff, b) in &glyph.0 {
+                        let (px, py) = (x + xoff, y + yoff);
+                        if *xoff < cell_width
+                            && *yoff < cell_height
+                            && px < pixels.width()
+                            && py < pixels.height()
+                        {
+                            pixels.set_brightness(px, py, *b);
+                        }
+                    }
+                }
             });
     }
 }
 
-pub struct StreamProcessor<'cap, 'out> {
-    cap_stream: MmapStream<'cap>,
-    out_stream: MmapStream<'out>,
-    filters: Vec<Box<dyn FrameFilter>>,
-    width: u32,
-    height: u32,
+impl FrameFilter for AsciiFilter<'_> {
+    fn process(&mut self, frame: &mut Frame<'_>) {
+        let (cell_width, cell_height) = self.size();
+        // Rasterizing keeps each pixel's own color, so skip averaging it
+        let grid = self.grid(frame, cell_width, cell_height, false);
+        self.rasterize(&grid, frame);
+    }
 }
 
-impl StreamProcessor<'_, '_> {
-    pub fn new(source: &str, sink: &str) -> anyhow::Result<Self> {
-        // Prepare capture and output devices
-        let cap = Device::with_path(source).context("Failed to open capture device")?;
-        let out = Device::with_path(sink).context("Failed to open output device")?;
+pub struct StreamProcessorBuilder<'path> {
+    source: &'path str,
+    sink: Option<&'path str>,
+    input_size: Option<(u32, u32)>,
+    input_fps: Option<u32>,
+    looping: bool,
+    size: Option<(u32, u32)>,
+    format: Option<PixelFormat>,
+}
 
-        // Confirm capture and output settings match and are valid
-        let mut cap_fmt = Capture::format(&cap).context("Failed to read capture format")?;
-        cap_fmt.fourcc = FourCC::new(b"YUYV");
-        let cap_fmt =
-            Capture::set_format(&cap, &cap_fmt).context("Failed to set capture format")?;
-        let out_fmt = Output::set_format(&out, &cap_fmt).context("Failed to set output format")?;
-        let cap_params = Capture::params(&cap).context("Failed to read capture parameters")?;
-        let out_params = Output::set_params(&out, &OutputParameters::new(cap_params.interval))
-            .context("Failed to set output parameters")?;
+impl<'path> StreamProcessorBuilder<'path> {
+    /// `source` is either a capture device, a .y4m or raw YUYV (.yuv) file,
+    /// or a test pattern such as `pattern:bars`.
+    #[must_use]
+    pub const fn new(source: &'path str, sink: &'path str) -> Self {
+        Self {
+            source,
+            sink: Some(sink),
+            input_size: None,
+            input_fps: None,
+            looping: false,
+            size: None,
+            format: None,
+        }
+    }
 
-        if cap_fmt.fourcc.str()? != "YUYV" {
-            return Err(anyhow!(
-                "Unsupported fourcc: {}",
-                cap_fmt.fourcc.str().unwrap()
-            ));
+    /// Discard processed frames, e.g. to only inspect them with
+    /// `StreamProcessor::process_frame_with`.
+    #[must_use]
+    pub const fn without_sink(source: &'path str) -> Self {
+        Self {
+            source,
+            sink: None,
+            input_size: None,
+            input_fps: None,
+            looping: false,
+            size: None,
+            format: None,
         }
+    }
 
-        if cap_fmt.width != out_fmt.width
-            || cap_fmt.height != out_fmt.height
-            || cap_fmt.fourcc != out_fmt.fourcc
-            || cap_params.interval.numerator != out_params.interval.numerator
-            || cap_params.interval.denominator != out_params.interval.denominator
-        {
-            return Err(anyhow!(
-                "Output parameters do not match capture:\n\
-                 Capture device:\n{}{}\nOutput device:\n{}{}",
-                cap_fmt,
-                cap_params,
-                out_fmt,
-                out_params,
-            ));
+    /// Set the frame size of a raw input file or test pattern.
+    #[must_use]
+    pub const fn with_input_size(mut self, width: u32, height: u32) -> Self {
+        self.input_size = Some((width, height));
+        self
+    }
+
+    #[must_use]
+    pub const fn with_input_size_or_default(self, size: Option<(u32, u32)>) -> Self {
+        if let Some((width, height)) = size {
+            self.with_input_size(width, height)
+        } else {
+            self
         }
+    }
 
-        println!(
-            "Capture device:\n{}{}{}\nOutput device:\n{}{}{}",
-            cap.query_caps()
-                .context("Failed to read capture capabilities")?,
-            cap_fmt,
-            cap_params,
-            out.query_caps()
-                .context("Failed to read output capabilities")?,
-            out_fmt,
-            out_params,
-        );
+    /// Set the frame rate of a raw input file or test pattern.
+    #[must_use]
+    pub const fn with_input_fps(mut self, fps: u32) -> Self {
+        self.input_fps = Some(fps);
+        self
+    }
 
-        // Prepare capture and output streams
-        let cap_stream =
-            MmapStream::new(&cap, Type::VideoCapture).context("Failed to open capture stream")?;
-        let out_stream =
-            MmapStream::new(&out, Type::VideoOutput).context("Failed to open output stream")?;
+    #[must_use]
+    pub const fn with_input_fps_or_default(self, fps: Option<u32>) -> Self {
+        if let Some(fps) = fps {
+            self.with_input_fps(fps)
+        } else {
+            self
+        }
+    }
 
-        Ok(Self {
-            cap_stream,
-            out_stream,
-            filters: vec![],
-            width: cap_fmt.width,
-            height: cap_fmt.height,
+    /// Restart input files from the beginning when they end.
+    #[must_use]
+    pub const fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_output_size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    #[must_use]
+    pub const fn with_output_size_or_default(self, size: Option<(u32, u32)>) -> Self {
+        if let Some((width, height)) = size {
+            self.with_output_size(width, height)
+        } else {
+            self
+        }
+    }
+
+    #[must_use]
+    pub const fn with_output_format(mut self, format: PixelFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    #[must_use]
+    pub const fn with_output_format_or_default(self, format: Option<PixelFormat>) -> Self {
+        if let Some(format) = format {
+            self.with_output_format(format)
+        } else {
+            self
+        }
+    }
+
+    pub fn build<'cap, 'out>(self) -> anyhow::Result<BoxedStreamProcessor<'cap, 'out>> {
+        let (source, source_description) = self.open_source()?;
+        let (width, height, format) = self.output_params(&source);
+        let (sink, sink_description) = self.open_sink(width, height, format, source.interval())?;
+
+        println!("Capture:\n{source_description}\nOutput:\n{sink_description}");
+
+        Ok(StreamProcessor::new(source, sink))
+    }
+
+    /// Like `build`, but output to the sink returned by `open_sink`, which is
+    /// passed the output size, format and frame interval. Nothing is printed,
+    /// so the sink may write to stdout.
+    pub fn build_with_sink<'cap, K, F>(
+        self,
+        open_sink: F,
+    ) -> anyhow::Result<StreamProcessor<Box<dyn FrameSource + 'cap>, K>>
+    where
+        K: FrameSink,
+        F: FnOnce(u32, u32, PixelFormat, Fraction) -> anyhow::Result<K>,
+    {
+        let (source, _) = self.open_source()?;
+        let (width, height, format) = self.output_params(&source);
+        let sink = open_sink(width, height, format, source.interval())?;
+        Ok(StreamProcessor::new(source, sink))
+    }
+
+    /// The output size and format, defaulting to those of `source`.
+    fn output_params<S>(&self, source: &S) -> (u32, u32, PixelFormat)
+    where
+        S: FrameSource,
+    {
+        let (width, height) = self.size.unwrap_or((source.width(), source.height()));
+        (width, height, self.format.unwrap_or(source.format()))
+    }
+
+    /// Open `source` as a test pattern if it starts with `pattern:`, as a
+    /// file if it has a .y4m or .yuv extension, or as a capture device
+    /// otherwise.
+    fn open_source<'cap>(&self) -> anyhow::Result<(Box<dyn FrameSource + 'cap>, String)> {
+        if let Some(name) = self.source.strip_prefix("pattern:") {
+            let (width, height) = self.input_size.unwrap_or(DEFAULT_PATTERN_SIZE);
+            let fps = self.input_fps.unwrap_or(DEFAULT_INPUT_FPS);
+            let pattern = PatternSource::new(name.parse()?, width, height)
+                .with_interval(Fraction::new(1, fps));
+            let description = pattern.to_string();
+            return Ok((Box::new(pattern), description));
+        }
+
+        Ok(match extension(self.source) {
+            Some("y4m") => {
+                let file = FileSource::y4m(self.source)?.with_looping(self.looping);
+                let description = file.to_string();
+                (Box::new(file), description)
+            }
+            Some("yuv" | "raw") => {
+                // Raw files carry no header, so they are assumed to be YUYV
+                let (width, height) = self
+                    .input_size
+                    .context("Raw input files require an input size")?;
+                let fps = self.input_fps.unwrap_or(DEFAULT_INPUT_FPS);
+                let file = FileSource::raw(
+                    self.source,
+                    width,
+                    height,
+                    PixelFormat::Yuyv,
+                    Fraction::new(1, fps),
+                )?
+                .with_looping(self.looping);
+                let description = file.to_string();
+                (Box::new(file), description)
+            }
+            _ => {
+                let device = DeviceSource::new(self.source)?;
+                let description = device.to_string();
+                (Box::new(device), description)
+            }
         })
     }
 
+    /// Create `sink` as a file if it has a .y4m or .yuv extension, or open it
+    /// as an output device otherwise.
+    fn open_sink<'out>(
+        &self,
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        interval: Fraction,
+    ) -> anyhow::Result<(Box<dyn FrameSink + 'out>, String)> {
+        let Some(sink) = self.sink else {
+            return Ok((
+                Box::new(NullSink::new(width, height, format)),
+                "none\n".into(),
+            ));
+        };
+        Ok(match extension(sink) {
+            Some("y4m") => {
+                let file = FileSink::y4m(sink, width, height, format, interval)?;
+                let description = file.to_string();
+                (Box::new(file), description)
+            }
+            Some("yuv" | "raw") => {
+                let file = FileSink::raw(sink, width, height, format)?;
+                let description = file.to_string();
+                (Box::new(file), description)
+            }
+            _ => {
+                let device = DeviceSink::new(sink, width, height, format, interval)?;
+                let description = device.to_string();
+                (Box::new(device), description)
+            }
+        })
+    }
+}
+
+/// A `StreamProcessor` over sources and sinks picked at runtime.
+pub type BoxedStreamProcessor<'cap, 'out> =
+    StreamProcessor<Box<dyn FrameSource + 'cap>, Box<dyn FrameSink + 'out>>;
+
+pub struct StreamProcessor<S, K> {
+    source: S,
+    sink: K,
+    filters: Vec<Box<dyn FrameFilter>>,
+    recorder: Option<Recorder>,
+    /// Stopped recordings still being written, and the last one.
+    encodings: Vec<Encoding>,
+    snapshot: Option<PathBuf>,
+    /// Why the last snapshot failed to save.
+    snapshot_error: Option<anyhow::Error>,
+}
+
+impl<S, K> StreamProcessor<S, K>
+where
+    S: FrameSource,
+    K: FrameSink,
+{
+    #[must_use]
+    pub const fn new(source: S, sink: K) -> Self {
+        Self {
+            source,
+            sink,
+            filters: vec![],
+            recorder: None,
+            encodings: vec![],
+            snapshot: None,
+            snapshot_error: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn source(&self) -> &S {
+        &self.source
+    }
+
+    #[must_use]
+    pub const fn sink(&self) -> &K {
+        &self.sink
+    }
+
     #[must_use]
     pub fn add_filter(mut self, filter: Box<dyn FrameFilter>) -> Self {
         self.filters.push(filter);
@@ -648,29 +1456,123 @@ impl StreamProcessor<'_, '_> {
         self
     }
 
-    pub fn process_frame(&mut self) -> anyhow::Result<()> {
+    /// Record processed frames with `recorder` until it is full or
+    /// `stop_recording` is called, stopping any current recording first.
+    pub fn start_recording(&mut self, recorder: Recorder) {
+        self.stop_recording();
+        self.recorder = Some(recorder);
+    }
+
+    /// Stop the current recording, if any, and write it out in the
+    /// background.
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.encodings.push(recorder.stop());
+        }
+    }
+
+    /// Stop the current recording and wait for every recording to be
+    /// written out.
+    pub fn finish_recordings(&mut self) -> anyhow::Result<()> {
+        self.stop_recording();
+        self.encodings
+            .drain(..)
+            .map(Encoding::wait)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub const fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// The last recording stopped, while or after it is written out.
+    #[must_use]
+    pub fn encoding(&self) -> Option<&Encoding> {
+        self.encodings.last()
+    }
+
+    /// Collect the outcome of recordings written out, keeping the last one.
+    fn poll_encodings(&mut self) {
+        for encoding in &mut self.encodings {
+            encoding.poll();
+        }
+        let last = self.encodings.pop();
+        self.encodings.retain(|encoding| !encoding.is_done());
+        self.encodings.extend(last);
+    }
+
+    /// Save the next processed frame to `path` as an image, replacing any
+    /// pending snapshot.
+    pub fn save_snapshot<P>(&mut self, path: P)
+    where
+        P: Into<PathBuf>,
+    {
+        self.snapshot = Some(path.into());
+        self.snapshot_error = None;
+    }
+
+    /// Whether a snapshot will be saved from the next processed frame.
+    #[must_use]
+    pub const fn snapshot_pending(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// Why the last snapshot failed to save, if it did. Failing to save a
+    /// snapshot doesn't stop the stream.
+    #[must_use]
+    pub const fn snapshot_error(&self) -> Option<&anyhow::Error> {
+        self.snapshot_error.as_ref()
+    }
+
+    /// Process the next frame. Returns `false` once the source has no more
+    /// frames.
+    pub fn process_frame(&mut self) -> anyhow::Result<bool> {
+        self.process_frame_with(|_| {})
+    }
+
+    /// Like `process_frame`, but also passes the captured frame to `inspect`
+    /// before it is filtered.
+    pub fn process_frame_with<F>(&mut self, inspect: F) -> anyhow::Result<bool>
+    where
+        F: FnOnce(&Frame<'_>),
+    {
         // Get the next frame
-        let (buf_in, meta_in) =
-            CaptureStream::next(&mut self.cap_stream).context("Failed to read capture frame")?;
-        let (buf_out, meta_out) =
-            OutputStream::next(&mut self.out_stream).context("Failed to read output frame")?;
+        let (width, height, format) = (
+            self.source.width(),
+            self.source.height(),
+            self.source.format(),
+        );
+        let mut buf = vec![0; format.frame_size(width, height)];
+        if !self.source.read_frame(&mut buf)? {
+            return Ok(false);
+        }
 
         // Process the frame
-        let mut buf = buf_in.to_vec();
-        let mut frame = Frame::new(&mut buf, self.width, self.height);
-        for filter in &self.filters {
+        let mut frame = Frame::with_format(&mut buf, width, height, format);
+        inspect(&frame);
+        for filter in &mut self.filters {
             filter.process(&mut frame);
         }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&frame);
+            if recorder.is_full() {
+                self.stop_recording();
+            }
+        }
+        self.poll_encodings();
+        if let Some(path) = self.snapshot.take() {
+            self.snapshot_error = frame.save_image(path).err();
+        }
 
-        // Output the processed frame
-        let buf_out = &mut buf_out[..buf_in.len()];
-        buf_out.copy_from_slice(frame.as_bytes());
-
-        // Set metadata
-        // https://www.kernel.org/doc/html/v4.15/media/uapi/v4l/buffer.html#struct-v4l2-buffer
-        meta_out.field = 0;
-        meta_out.bytesused = meta_in.bytesused;
+        // Convert and output the processed frame
+        self.sink.write_frame(&frame)?;
 
-        Ok(())
+        Ok(true)
     }
 }
+
+fn extension(path: &str) -> Option<&str> {
+    Path::new(path).extension().and_then(|ext| ext.to_str())
+}
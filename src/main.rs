@@ -10,12 +10,15 @@
 #![warn(clippy::use_self)]
 #![warn(clippy::if_then_some_else_none)]
 
-use std::io;
-use std::path::PathBuf;
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use clap::Parser;
 use crossterm::{
     event::{self, Event as TEvent, KeyCode, KeyEvent, KeyModifiers},
@@ -23,31 +26,78 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use tui::{
-    backend::CrosstermBackend,
-    layout::Constraint,
-    style::{Modifier, Style},
-    text::Span,
-    widgets::{Block, Row, Table},
-    Terminal,
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Paragraph, Row, Table},
+    Frame, Terminal,
 };
 
-use asciime_filter::{charset, AsciiFilter, AsciiMap, AsciiMode, GlyphMapBuilder, StreamProcessor};
+use asciime_filter::{
+    charset, AsciiFilter, AsciiGrid, AsciiMap, AsciiMode, BlockCharset, BoxedStreamProcessor,
+    Dithering, DocumentFormat, DocumentSink, Exposure, FrameSink, FrameSource, GlyphMapBuilder,
+    GlyphMatching, PixelFormat, Recorder, StreamProcessorBuilder, TextColor, TextSink, ToneMap,
+};
 
 const SIZE_INCREMENT: i32 = 1;
 const BIG_SIZE_INCREMENT: i32 = 10;
+const BRIGHTNESS_INCREMENT: i32 = 8;
+const CONTRAST_INCREMENT: f32 = 0.1;
+const GAMMA_INCREMENT: f32 = 0.1;
+const LEVEL_INCREMENT: u8 = 8;
+/// Height of the parameter table including its title.
+const PARAMS_HEIGHT: u16 = 16;
+/// Terminal cells are roughly twice as tall as they are wide.
+const CELL_ASPECT: u32 = 2;
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub struct Opts {
     #[clap()]
-    /// Path to the capture device
+    /// Path to the capture device, a .y4m or raw YUYV (.yuv) file, or a test
+    /// pattern: pattern:{bars,gradient,checkerboard,zoneplate,noise}
     source: String,
-    #[clap()]
-    /// Path to the output device
-    sink: String,
+    #[clap(required_unless_present_any = ["terminal", "record"])]
+    /// Path to the output device, a .y4m or raw (.yuv) file to record to, a
+    /// .txt file or - (stdout) to write text to, or a .html or .svg file to
+    /// save a snapshot to
+    sink: Option<String>,
     #[clap(short = 'b', long = "bitdepth", default_value_t = 6)]
     /// Number of bits to use for the charset
     nbits: u32,
+    #[clap(short = 'c', long = "charset", conflicts_with = "nbits")]
+    /// Characters to use, from the darkest cells to the brightest, e.g.
+    /// "@%#*+=-:. "
+    charset: Option<String>,
+    #[clap(long = "charset-file", conflicts_with_all = ["nbits", "charset"])]
+    /// Path to a file with the characters to use, from the darkest cells to
+    /// the brightest, ignoring line breaks
+    charset_file: Option<PathBuf>,
+    #[clap(
+        long = "blocks",
+        value_enum,
+        conflicts_with_all = ["nbits", "charset", "charset_file"]
+    )]
+    /// Draw with Unicode block elements: shades by brightness, or half or
+    /// quadrant blocks by the brightness of each part of a cell, for twice
+    /// the resolution
+    blocks: Option<Blocks>,
+    #[clap(
+        long = "braille",
+        conflicts_with_all = ["nbits", "charset", "charset_file", "blocks"]
+    )]
+    /// Draw with braille patterns, a dot for each part of a 2x4 grid in a
+    /// cell, for eight times the resolution
+    braille: bool,
+    #[clap(long = "sort-charset")]
+    /// Order the charset by how much ink each character has in the font
+    sort_charset: bool,
+    #[clap(long = "resample-charset")]
+    /// Keep this many characters of the sorted charset, with evenly spaced
+    /// amounts of ink
+    resample_charset: Option<usize>,
     #[clap(short = 'f', long = "font")]
     /// Path to a font
     font: Option<PathBuf>,
@@ -57,9 +107,73 @@ pub struct Opts {
     #[clap(short = 'm', long = "mode", value_enum, default_value_t = Mode::Color)]
     /// Color mode
     mode: Mode,
+    #[clap(long = "match", value_enum, default_value_t = Matching::Brightness)]
+    /// How characters are picked: by average brightness, or by matching
+    /// glyph shapes to the image, e.g. to draw edges with / | -
+    matching: Matching,
+    #[clap(long = "dither", value_enum, default_value_t = Dither::None)]
+    /// Dithering of characters picked by brightness, keeping gradients
+    /// smooth at low bit depths
+    dither: Dither,
+    #[clap(long = "exposure", value_enum, default_value_t = AutoExposure::Fixed)]
+    /// Adapt cell brightness to the lighting of each frame by stretching or
+    /// equalizing its histogram
+    exposure: AutoExposure,
+    #[clap(long = "smoothing", default_value_t = 0.0)]
+    /// How much cells keep of their brightness in previous frames, from 0
+    /// to 0.99, to stop characters flickering with camera noise. Off by default
+    smoothing: f32,
+    #[clap(
+        long = "brightness",
+        default_value_t = 0,
+        allow_negative_numbers = true
+    )]
+    /// Brightness offset of cells before picking characters, from -255 to
+    /// 255
+    brightness: i32,
+    #[clap(long = "contrast", default_value_t = 1.0)]
+    /// Contrast of cells around mid-gray, 1 to leave it unchanged
+    contrast: f32,
+    #[clap(long = "gamma", default_value_t = 1.0)]
+    /// Gamma of cells, above 1 to brighten the midtones
+    gamma: f32,
+    #[clap(long = "black-point", default_value_t = 0)]
+    /// Cell brightness mapped to black
+    black_point: u8,
+    #[clap(long = "white-point", default_value_t = 255)]
+    /// Cell brightness mapped to white
+    white_point: u8,
+    #[clap(long = "input-size", value_parser = parse_size)]
+    /// Frame size of a raw input file or test pattern, e.g. 1280x720
+    input_size: Option<(u32, u32)>,
+    #[clap(long = "input-fps", value_parser = clap::value_parser!(u32).range(1..))]
+    /// Frame rate of a raw input file or test pattern (defaults to 30)
+    input_fps: Option<u32>,
+    #[clap(short = 'l', long = "loop")]
+    /// Restart input files when they end
+    looping: bool,
+    #[clap(short = 'S', long = "output-size", value_parser = parse_size)]
+    /// Output frame size, e.g. 1280x720 (defaults to the capture size)
+    output_size: Option<(u32, u32)>,
+    #[clap(short = 'F', long = "output-format", value_enum)]
+    /// Output pixel format (defaults to the capture format)
+    output_format: Option<Format>,
     #[clap(short = 'I', long = "no-interactive")]
     /// Disable interactive mode
     nointeractive: bool,
+    #[clap(short = 't', long = "terminal", conflicts_with = "nointeractive")]
+    /// Preview the ASCII art in the terminal, colored unless in grayscale mode
+    terminal: bool,
+    #[clap(long = "text-color", value_enum, default_value_t = Coloring::Plain)]
+    /// Color escape codes of text output
+    text_color: Coloring,
+    #[clap(short = 'r', long = "record")]
+    /// Record the ASCII output to an animated .gif or .png (APNG) file,
+    /// exiting when done if there is no output or preview
+    record: Option<PathBuf>,
+    #[clap(long = "record-secs", default_value_t = 10)]
+    /// Maximum length of a recording (seconds)
+    record_secs: u64,
 }
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
@@ -67,15 +181,140 @@ enum Mode {
     Grayscale,
     Color,
     Invert,
+    Edges,
 }
 
 impl From<Mode> for AsciiMode {
-    #[must_use]
     fn from(mode: Mode) -> Self {
         match mode {
             Mode::Grayscale => Self::Grayscale,
             Mode::Color => Self::Color,
             Mode::Invert => Self::Invert,
+            Mode::Edges => Self::Edges,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Blocks {
+    Shades,
+    Half,
+    Quadrants,
+}
+
+impl From<Blocks> for BlockCharset {
+    fn from(blocks: Blocks) -> Self {
+        match blocks {
+            Blocks::Shades => Self::Shades,
+            Blocks::Half => Self::HalfBlocks,
+            Blocks::Quadrants => Self::Quadrants,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Matching {
+    Brightness,
+    Shape,
+}
+
+impl From<Matching> for GlyphMatching {
+    fn from(matching: Matching) -> Self {
+        match matching {
+            Matching::Brightness => Self::Brightness,
+            Matching::Shape => Self::Shape,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Dither {
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Bayer,
+}
+
+impl From<Dither> for Dithering {
+    fn from(dither: Dither) -> Self {
+        match dither {
+            Dither::None => Self::None,
+            Dither::FloydSteinberg => Self::FloydSteinberg,
+            Dither::Atkinson => Self::Atkinson,
+            Dither::Bayer => Self::Bayer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum AutoExposure {
+    Fixed,
+    Stretch,
+    Equalize,
+}
+
+impl From<AutoExposure> for Exposure {
+    fn from(exposure: AutoExposure) -> Self {
+        match exposure {
+            AutoExposure::Fixed => Self::Fixed,
+            AutoExposure::Stretch => Self::Stretch,
+            AutoExposure::Equalize => Self::Equalize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Coloring {
+    Plain,
+    Ansi256,
+    Truecolor,
+}
+
+impl From<Coloring> for TextColor {
+    fn from(coloring: Coloring) -> Self {
+        match coloring {
+            Coloring::Plain => Self::Plain,
+            Coloring::Ansi256 => Self::Ansi256,
+            Coloring::Truecolor => Self::TrueColor,
+        }
+    }
+}
+
+fn parse_size(size: &str) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = size
+        .split_once('x')
+        .context("Expected a size of the form WIDTHxHEIGHT")?;
+    Ok((
+        width.parse().context("Invalid width")?,
+        height.parse().context("Invalid height")?,
+    ))
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Format {
+    Yuyv,
+    Nv12,
+    Nv21,
+    I420,
+    Yv12,
+    Rgb24,
+    Bgr24,
+    Rgba32,
+    Xrgb32,
+}
+
+impl From<Format> for PixelFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Yuyv => Self::Yuyv,
+            Format::Nv12 => Self::Nv12,
+            Format::Nv21 => Self::Nv21,
+            Format::I420 => Self::I420,
+            Format::Yv12 => Self::Yv12,
+            Format::Rgb24 => Self::Rgb24,
+            Format::Bgr24 => Self::Bgr24,
+            Format::Rgba32 => Self::Rgba32,
+            Format::Xrgb32 => Self::Xrgb32,
         }
     }
 }
@@ -86,24 +325,41 @@ enum MoreLess {
     Less,
 }
 
+#[derive(Debug, Clone, Copy)]
+enum ToneControl {
+    Brightness,
+    Contrast,
+    Gamma,
+    BlackPoint,
+    WhitePoint,
+}
+
 #[non_exhaustive]
 #[derive(Debug, Clone, Copy)]
 enum Event {
     Quit,
     Toggle,
     CycleMode,
+    CycleDithering,
+    CycleExposure,
+    ToggleRecording,
+    Snapshot,
     ChangeSize(i32),
     ChangeBitdepth(MoreLess),
+    ChangeTone(ToneControl, MoreLess),
     Other,
 }
 
 impl From<KeyEvent> for Event {
-    #[must_use]
     fn from(key: KeyEvent) -> Self {
         match key.code {
             KeyCode::Char('q') | KeyCode::Esc => Self::Quit,
             KeyCode::Char(' ') => Self::Toggle,
             KeyCode::Enter => Self::CycleMode,
+            KeyCode::Char('d') => Self::CycleDithering,
+            KeyCode::Char('e') => Self::CycleExposure,
+            KeyCode::Char('r') => Self::ToggleRecording,
+            KeyCode::Char('s') => Self::Snapshot,
             KeyCode::Char(c @ ('+' | '-')) => {
                 let sign = if c == '+' { 1 } else { -1 };
                 let inc = if key.modifiers.contains(KeyModifiers::ALT) {
@@ -115,59 +371,258 @@ impl From<KeyEvent> for Event {
             }
             KeyCode::Left => Self::ChangeBitdepth(MoreLess::Less),
             KeyCode::Right => Self::ChangeBitdepth(MoreLess::More),
+            KeyCode::Up => Self::ChangeTone(ToneControl::Brightness, MoreLess::More),
+            KeyCode::Down => Self::ChangeTone(ToneControl::Brightness, MoreLess::Less),
+            KeyCode::Char(c @ ('c' | 'C' | 'g' | 'G' | 'k' | 'K' | 'w' | 'W')) => {
+                let control = match c.to_ascii_lowercase() {
+                    'c' => ToneControl::Contrast,
+                    'g' => ToneControl::Gamma,
+                    'k' => ToneControl::BlackPoint,
+                    _ => ToneControl::WhitePoint,
+                };
+                let moreless = if c.is_ascii_uppercase() {
+                    MoreLess::More
+                } else {
+                    MoreLess::Less
+                };
+                Self::ChangeTone(control, moreless)
+            }
             _ => Self::Other,
         }
     }
 }
 
+#[allow(clippy::struct_excessive_bools)]
 struct AppState<'cap, 'out> {
     source: String,
-    sink: String,
-    nbits: u32,
-    chars: Vec<char>,
+    sink: Option<String>,
+    /// The bit depth of the built-in charset, if it is used.
+    nbits: Option<u32>,
+    /// The charset as loaded, before any sorting.
+    charset: AsciiMap,
+    sort_charset: bool,
+    /// How many characters of the sorted charset to keep, if not all.
+    resample: Option<usize>,
     ascii_filter: AsciiFilter<'static>,
-    stream: StreamProcessor<'cap, 'out>,
+    stream: BoxedStreamProcessor<'cap, 'out>,
     interactive: bool,
+    terminal: bool,
+    /// Whether the sink maps frames to characters itself, as text and
+    /// document sinks do.
+    sink_renders: bool,
+    /// Whether the stream filters frames with the ASCII filter.
+    filtering: bool,
+    snapshot: bool,
+    record_secs: u64,
+    record_only: bool,
+    snapshot_path: Option<String>,
+    /// Why the last recording failed to start.
+    record_error: Option<String>,
     enabled: bool,
     redraw: bool,
 }
 
 impl AppState<'_, '_> {
     fn from_opts(opts: Opts) -> anyhow::Result<Self> {
-        let nbits = opts.nbits;
-        let chars = charset(nbits).context("No charset for that number of bits")?;
-        let glyphs = GlyphMapBuilder::new(&chars)
+        let (nbits, ascii_map) = load_charset(&opts)?;
+        let glyphs = GlyphMapBuilder::new(ascii_map.chars())
             .with_font_or_default(opts.font)
             .with_size_or_default(opts.font_size)
             .build()?;
-        let ascii_map = AsciiMap::new(chars.clone());
 
-        let ascii_filter = AsciiFilter::new(ascii_map, glyphs, opts.mode.into());
-        let stream = StreamProcessor::new(&opts.source, &opts.sink)?
-            .add_filter(Box::new(ascii_filter.clone()));
+        let charset = ascii_map.clone();
+        let mut ascii_filter = AsciiFilter::new(ascii_map, glyphs, opts.mode.into())
+            .with_matching(opts.matching.into())
+            .with_dithering(opts.dither.into())
+            .with_exposure(opts.exposure.into())
+            .with_smoothing(opts.smoothing)
+            .with_tone(
+                ToneMap::new()
+                    .with_brightness(opts.brightness)
+                    .with_contrast(opts.contrast)
+                    .with_gamma(opts.gamma)
+                    .with_levels(opts.black_point, opts.white_point),
+            );
+        let sort_charset = opts.sort_charset || opts.resample_charset.is_some();
+        if sort_charset {
+            ascii_filter = ascii_filter.sort_charset(opts.resample_charset);
+        }
+        let builder = if let Some(sink) = &opts.sink {
+            StreamProcessorBuilder::new(&opts.source, sink)
+        } else {
+            StreamProcessorBuilder::without_sink(&opts.source)
+        }
+        .with_input_size_or_default(opts.input_size)
+        .with_input_fps_or_default(opts.input_fps)
+        .with_looping(opts.looping)
+        .with_output_size_or_default(opts.output_size)
+        .with_output_format_or_default(opts.output_format.map(Into::into));
+
+        // Text sinks map frames to characters themselves and aren't
+        // interactive, since they may write to stdout
+        let text_sink = opts.sink.as_deref().filter(|sink| {
+            *sink == "-" || Path::new(sink).extension().is_some_and(|ext| ext == "txt")
+        });
+        // Document sinks save a single frame
+        let document = opts.sink.as_deref().and_then(document_format);
+        let sink_renders = text_sink.is_some() || document.is_some();
+        if sink_renders && opts.record.is_some() {
+            return Err(anyhow!("Text output and snapshots can't be recorded"));
+        }
+        let interactive = !opts.nointeractive && !sink_renders;
+        // Recording alone stops when the recording does
+        let record_only = opts.sink.is_none() && !opts.terminal;
+        let stream = if let Some(sink) = text_sink {
+            if opts.terminal {
+                return Err(anyhow!("Text output can't be previewed in the terminal"));
+            }
+            let writer: Box<dyn Write> = if sink == "-" {
+                Box::new(io::stdout())
+            } else {
+                let file =
+                    File::create(sink).with_context(|| format!("Failed to create {sink}"))?;
+                Box::new(BufWriter::new(file))
+            };
+            let text_filter = ascii_filter.clone();
+            let color = opts.text_color.into();
+            builder.build_with_sink(|width, height, _, _| {
+                let sink: Box<dyn FrameSink> =
+                    Box::new(TextSink::new(writer, text_filter, color, width, height));
+                Ok(sink)
+            })?
+        } else if let (Some(sink), Some(document)) = (&opts.sink, document) {
+            if opts.terminal {
+                return Err(anyhow!("Snapshots can't be previewed in the terminal"));
+            }
+            let doc_filter = ascii_filter.clone();
+            builder.build_with_sink(|width, height, _, _| {
+                let sink: Box<dyn FrameSink> =
+                    Box::new(DocumentSink::new(sink, document, doc_filter, width, height));
+                Ok(sink)
+            })?
+        } else {
+            builder.build()?
+        };
 
-        Ok(Self {
+        let mut app = Self {
             source: opts.source,
             sink: opts.sink,
             nbits,
-            chars,
+            charset,
+            sort_charset,
+            resample: opts.resample_charset,
             ascii_filter,
             stream,
-            interactive: !opts.nointeractive,
+            interactive,
+            terminal: opts.terminal,
+            sink_renders,
+            filtering: false,
+            snapshot: document.is_some(),
+            record_secs: opts.record_secs,
+            record_only,
+            snapshot_path: None,
+            record_error: None,
             enabled: true,
             redraw: true,
-        })
+        };
+        if let Some(path) = opts.record {
+            app.start_recording(path)?;
+        }
+        Ok(app.update_filters())
     }
 
+    /// Record the processed frames at the capture size and frame rate.
+    fn start_recording<P>(&mut self, path: P) -> anyhow::Result<()>
+    where
+        P: Into<PathBuf>,
+    {
+        let source = self.stream.source();
+        let recorder = Recorder::new(path, source.width(), source.height(), source.interval())?
+            .with_duration(Duration::from_secs(self.record_secs));
+        self.stream.start_recording(recorder);
+        Ok(())
+    }
+
+    /// Start recording to a timestamped GIF, or finish the current
+    /// recording.
     #[must_use]
-    fn toggle(mut self) -> Self {
+    fn toggle_recording(mut self) -> Self {
         self.redraw = true;
-        self.stream = if self.enabled {
-            self.stream.clear_filters()
+        if self.stream.recorder().is_some() {
+            self.stream.stop_recording();
         } else {
-            self.stream.add_filter(Box::new(self.ascii_filter.clone()))
-        };
+            self.record_error = self
+                .start_recording(format!("asciime-{}.gif", timestamp()))
+                .err()
+                .map(|err| format!("{err:#}"));
+        }
+        self.update_filters()
+    }
+
+    /// The current recording, or else how the last one went.
+    fn recording_status(&self) -> String {
+        if let Some(recorder) = self.stream.recorder() {
+            format!("{} ({} frames)", recorder.path().display(), recorder.len())
+        } else if let Some(err) = &self.record_error {
+            format!("failed: {err}")
+        } else if let Some(encoding) = self.stream.encoding() {
+            let path = encoding.path().display();
+            match (encoding.is_done(), encoding.error()) {
+                (false, _) => format!("saving {path}"),
+                (true, Some(err)) => format!("failed: {err:#}"),
+                (true, None) => format!("saved {path}"),
+            }
+        } else {
+            "off".into()
+        }
+    }
+
+    /// The last snapshot, or why it failed to save.
+    fn snapshot_status(&self) -> String {
+        match (&self.snapshot_path, self.stream.snapshot_error()) {
+            (_, Some(err)) => format!("failed: {err:#}"),
+            (Some(path), None) => path.clone(),
+            (None, None) => "none".into(),
+        }
+    }
+
+    /// Save the next processed frame to a timestamped PNG.
+    #[must_use]
+    fn snapshot(mut self) -> Self {
+        self.redraw = true;
+        let path = format!("asciime-{}.png", timestamp());
+        self.stream.save_snapshot(&path);
+        self.snapshot_path = Some(path);
+        self.update_filters()
+    }
+
+    #[must_use]
+    fn toggle(mut self) -> Self {
+        self.redraw = true;
         self.enabled = !self.enabled;
+        self.update_filters()
+    }
+
+    /// Whether the stream should filter frames with the ASCII filter. Text
+    /// and document sinks filter frames themselves, and the terminal preview
+    /// filters the captured frames, so frames only go through the stream's
+    /// filter when a sink, recording or snapshot takes them.
+    fn needs_filter(&self) -> bool {
+        self.enabled
+            && !self.sink_renders
+            && (self.sink.is_some()
+                || self.stream.recorder().is_some()
+                || self.stream.snapshot_pending())
+    }
+
+    /// Filter the stream's frames with the current ASCII filter, if needed.
+    #[must_use]
+    fn update_filters(mut self) -> Self {
+        self.stream = self.stream.clear_filters();
+        self.filtering = self.needs_filter();
+        if self.filtering {
+            self.stream = self.stream.add_filter(Box::new(self.ascii_filter.clone()));
+        }
         self
     }
 
@@ -175,49 +630,95 @@ impl AppState<'_, '_> {
     fn cycle_mode(mut self) -> Self {
         self.redraw = true;
         self.ascii_filter = self.ascii_filter.cycle_mode();
-        if self.enabled {
-            self.stream = self
-                .stream
-                .clear_filters()
-                .add_filter(Box::new(self.ascii_filter.clone()));
-        }
-        self
+        self.update_filters()
+    }
+
+    #[must_use]
+    fn cycle_dithering(mut self) -> Self {
+        self.redraw = true;
+        let dithering = self.ascii_filter.dithering().next();
+        self.ascii_filter = self.ascii_filter.with_dithering(dithering);
+        self.update_filters()
+    }
+
+    #[must_use]
+    fn cycle_exposure(mut self) -> Self {
+        self.redraw = true;
+        let exposure = self.ascii_filter.exposure().next();
+        self.ascii_filter = self.ascii_filter.with_exposure(exposure);
+        self.update_filters()
     }
 
     #[must_use]
     fn change_size(mut self, inc: i32) -> Self {
         self.redraw = true;
         self.ascii_filter = self.ascii_filter.resize(inc);
-        if self.enabled {
-            self.stream = self
-                .stream
-                .clear_filters()
-                .add_filter(Box::new(self.ascii_filter.clone()));
+        // How much ink glyphs have depends on their size
+        if self.sort_charset {
+            self = self.apply_charset();
         }
-        self
+        self.update_filters()
     }
 
     #[must_use]
     fn change_bitdepth(mut self, moreless: MoreLess) -> Self {
+        // Only built-in charsets have a bit depth
+        let Some(nbits) = self.nbits else {
+            return self;
+        };
         let new_nbits = match moreless {
-            MoreLess::More => self.nbits + 1,
-            MoreLess::Less => self.nbits - 1,
+            MoreLess::More => nbits + 1,
+            MoreLess::Less => nbits - 1,
         };
         if let Some(chars) = charset(new_nbits) {
             self.redraw = true;
-            self.nbits = new_nbits;
-            self.ascii_filter = self.ascii_filter.set_charset(chars.clone());
-            self.chars = chars;
-            if self.enabled {
-                self.stream = self
-                    .stream
-                    .clear_filters()
-                    .add_filter(Box::new(self.ascii_filter.clone()));
-            }
+            self.nbits = Some(new_nbits);
+            self.charset = AsciiMap::new(chars).expect("Invalid built-in charset");
+            self = self.apply_charset();
+            self = self.update_filters();
         }
         self
     }
 
+    /// Draw with the charset, sorted and resampled for the current glyphs if
+    /// asked to.
+    #[must_use]
+    fn apply_charset(mut self) -> Self {
+        self.ascii_filter = self.ascii_filter.set_charset(self.charset.clone());
+        if self.sort_charset {
+            self.ascii_filter = self.ascii_filter.sort_charset(self.resample);
+        }
+        self
+    }
+
+    #[must_use]
+    fn change_tone(mut self, control: ToneControl, moreless: MoreLess) -> Self {
+        self.redraw = true;
+        let sign: i8 = match moreless {
+            MoreLess::More => 1,
+            MoreLess::Less => -1,
+        };
+        let step = |level: u8| match moreless {
+            MoreLess::More => level.saturating_add(LEVEL_INCREMENT),
+            MoreLess::Less => level.saturating_sub(LEVEL_INCREMENT),
+        };
+        let tone = *self.ascii_filter.tone();
+        let (black_point, white_point) = tone.levels();
+        let tone = match control {
+            ToneControl::Brightness => {
+                tone.with_brightness(tone.brightness() + i32::from(sign) * BRIGHTNESS_INCREMENT)
+            }
+            ToneControl::Contrast => {
+                tone.with_contrast(tone.contrast() + f32::from(sign) * CONTRAST_INCREMENT)
+            }
+            ToneControl::Gamma => tone.with_gamma(tone.gamma() + f32::from(sign) * GAMMA_INCREMENT),
+            ToneControl::BlackPoint => tone.with_levels(step(black_point), white_point),
+            ToneControl::WhitePoint => tone.with_levels(black_point, step(white_point)),
+        };
+        self.ascii_filter = self.ascii_filter.with_tone(tone);
+        self.update_filters()
+    }
+
     #[must_use]
     fn font_size(&self) -> u32 {
         let (w, h) = self.ascii_filter.size();
@@ -231,6 +732,171 @@ impl AppState<'_, '_> {
     }
 }
 
+/// The custom, block or braille charset given in `opts`, or else the
+/// built-in one of the given bit depth along with that bit depth.
+fn load_charset(opts: &Opts) -> anyhow::Result<(Option<u32>, AsciiMap)> {
+    if let Some(chars) = &opts.charset {
+        Ok((None, AsciiMap::new(chars.chars().collect())?))
+    } else if let Some(path) = &opts.charset_file {
+        let chars = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read charset file {}", path.display()))?
+            .chars()
+            .filter(|c| !matches!(c, '\n' | '\r'))
+            .collect();
+        Ok((None, AsciiMap::new(chars)?))
+    } else if let Some(blocks) = opts.blocks {
+        Ok((None, AsciiMap::from_blocks(blocks.into())))
+    } else if opts.braille {
+        Ok((None, AsciiMap::braille()))
+    } else {
+        let chars = charset(opts.nbits).context("No charset for that number of bits")?;
+        Ok((Some(opts.nbits), AsciiMap::new(chars)?))
+    }
+}
+
+/// The document format a sink saves to, judging by its extension.
+fn document_format(sink: &str) -> Option<DocumentFormat> {
+    match Path::new(sink).extension()?.to_str()? {
+        "html" => Some(DocumentFormat::Html),
+        "svg" => Some(DocumentFormat::Svg),
+        _ => None,
+    }
+}
+
+/// The current UTC time as YYYYmmdd-HHMMSS-mmm, with milliseconds so that
+/// files named after it in quick succession differ.
+fn timestamp() -> String {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let (secs, millis) = (time.as_secs(), time.subsec_millis());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let doe = days % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}-{millis:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// The size of the frame cells that fit the frame into `cols` x `rows`
+/// terminal cells while keeping its aspect ratio.
+fn preview_cell_size(width: u32, height: u32, cols: u32, rows: u32) -> (u32, u32) {
+    let cell_width = cmp::max(
+        width.div_ceil(cols.max(1)),
+        height.div_ceil(CELL_ASPECT * rows.max(1)),
+    )
+    .max(1);
+    (cell_width, CELL_ASPECT * cell_width)
+}
+
+fn draw<B>(frame: &mut Frame<'_, B>, app: &AppState<'_, '_>, preview: Option<&AsciiGrid>)
+where
+    B: Backend,
+{
+    let status = if app.enabled { "Enabled" } else { "Disabled" };
+    let mode = match app.mode() {
+        AsciiMode::Grayscale => "grayscale",
+        AsciiMode::Color => "color",
+        AsciiMode::Invert => "invert",
+        AsciiMode::Edges => "edges",
+    };
+    let dithering = match app.ascii_filter.dithering() {
+        Dithering::None => "none",
+        Dithering::FloydSteinberg => "Floyd-Steinberg",
+        Dithering::Atkinson => "Atkinson",
+        Dithering::Bayer => "Bayer",
+    };
+    let font_size = app.font_size().to_string();
+    let nbits = app
+        .nbits
+        .map_or_else(|| "custom".into(), |nbits| nbits.to_string());
+    let exposure = match app.ascii_filter.exposure() {
+        Exposure::Fixed => "fixed",
+        Exposure::Stretch => "stretch",
+        Exposure::Equalize => "equalize",
+    };
+    let tone = app.ascii_filter.tone();
+    let brightness = format!("{:+}", tone.brightness());
+    let contrast = format!("{:.1}", tone.contrast());
+    let gamma = format!("{:.1}", tone.gamma());
+    let (black_point, white_point) = tone.levels();
+    let levels = format!("{black_point}-{white_point}");
+    let chars = app
+        .ascii_filter
+        .charset()
+        .iter()
+        .collect::<String>()
+        .replace(' ', "␣");
+    let recording = app.recording_status();
+    let snapshot = app.snapshot_status();
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(PARAMS_HEIGHT), Constraint::Min(0)])
+        .split(frame.size());
+    let params = Table::new(vec![
+        Row::new(vec!["source:", &app.source]),
+        Row::new(vec!["output:", app.sink.as_deref().unwrap_or("none")]),
+        Row::new(vec!["status (<SPACE>):", status]),
+        Row::new(vec!["mode (⏎):", mode]),
+        Row::new(vec!["dithering (d):", dithering]),
+        Row::new(vec!["size (+/-):", &font_size]),
+        Row::new(vec!["bit depth (⬅/➡):", &nbits]),
+        Row::new(vec!["exposure (e):", exposure]),
+        Row::new(vec!["brightness (↑/↓):", &brightness]),
+        Row::new(vec!["contrast (c/C):", &contrast]),
+        Row::new(vec!["gamma (g/G):", &gamma]),
+        Row::new(vec!["levels (k/K,w/W):", &levels]),
+        Row::new(vec!["charset:", &chars]),
+        Row::new(vec!["record (r):", &recording]),
+        Row::new(vec!["snapshot (s):", &snapshot]),
+    ])
+    .block(Block::default().title(Span::styled(
+        "Parameters (Controls)",
+        Style::default().add_modifier(Modifier::BOLD),
+    )))
+    .widths(&[Constraint::Length(17), Constraint::Length(64)]);
+    frame.render_widget(params, chunks[0]);
+
+    if let Some(preview) = preview.filter(|_| app.enabled) {
+        let lines = preview
+            .iter_rows()
+            .map(|row| {
+                Spans::from(
+                    row.iter()
+                        .map(|cell| {
+                            let style =
+                                cell.rgb.map_or_else(Style::default, |(red, green, blue)| {
+                                    Style::default().fg(Color::Rgb(red, green, blue))
+                                });
+                            Span::styled(cell.c.to_string(), style)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(lines), chunks[1]);
+    }
+}
+
+/// The terminal cells left for the preview below the parameters.
+fn preview_area<B: Backend>(terminal: &Terminal<B>) -> anyhow::Result<(u32, u32)> {
+    let size = terminal.size().context("Failed to read terminal size")?;
+    let rows = size.height.saturating_sub(PARAMS_HEIGHT);
+    Ok((u32::from(size.width), u32::from(rows)))
+}
+
 fn main() -> anyhow::Result<()> {
     let opts = Opts::parse();
     let mut app = AppState::from_opts(opts)?;
@@ -255,7 +921,30 @@ fn main() -> anyhow::Result<()> {
     }
 
     loop {
-        app.stream.process_frame()?;
+        let mut preview = None;
+        let status = (app.recording_status(), app.snapshot_status());
+        // The preview is blank while the filter is disabled
+        let more = if app.terminal && app.enabled {
+            let (cols, rows) = preview_area(terminal.as_ref().unwrap())?;
+            let ascii_filter = &mut app.ascii_filter;
+            app.stream.process_frame_with(|frame| {
+                let (cell_width, cell_height) =
+                    preview_cell_size(frame.width(), frame.height(), cols, rows);
+                preview = Some(ascii_filter.to_grid(frame, cell_width, cell_height));
+            })?
+        } else {
+            app.stream.process_frame()?
+        };
+        if (app.recording_status(), app.snapshot_status()) != status {
+            app.redraw = true;
+        }
+        // Recordings stop and snapshots are saved while processing frames
+        if app.needs_filter() != app.filtering {
+            app = app.update_filters();
+        }
+        if !more || app.snapshot || (app.record_only && app.stream.recorder().is_none()) {
+            break;
+        }
         if app.interactive {
             if let Ok(ev) = rx.try_recv() {
                 match ev {
@@ -268,48 +957,35 @@ fn main() -> anyhow::Result<()> {
                     Event::CycleMode => {
                         app = app.cycle_mode();
                     }
+                    Event::CycleDithering => {
+                        app = app.cycle_dithering();
+                    }
+                    Event::CycleExposure => {
+                        app = app.cycle_exposure();
+                    }
+                    Event::ToggleRecording => {
+                        app = app.toggle_recording();
+                    }
+                    Event::Snapshot => {
+                        app = app.snapshot();
+                    }
                     Event::ChangeSize(inc) => {
                         app = app.change_size(inc);
                     }
                     Event::ChangeBitdepth(moreless) => {
                         app = app.change_bitdepth(moreless);
                     }
+                    Event::ChangeTone(control, moreless) => {
+                        app = app.change_tone(control, moreless);
+                    }
                     _ => {}
                 }
             }
-            if app.redraw {
+            if app.redraw || preview.is_some() {
                 terminal
                     .as_mut()
                     .unwrap()
-                    .draw(|frame| {
-                        let status = if app.enabled { "Enabled" } else { "Disabled" };
-                        let mode = match app.mode() {
-                            AsciiMode::Grayscale => "grayscale",
-                            AsciiMode::Color => "color",
-                            AsciiMode::Invert => "invert",
-                        };
-                        let font_size = app.font_size().to_string();
-                        let nbits = app.nbits.to_string();
-                        let chars = app.chars.iter().collect::<String>().replace(' ', "␣");
-
-                        let size = frame.size();
-                        let params = Table::new(vec![
-                            Row::new(vec!["capture:", &app.source]),
-                            Row::new(vec!["output:", &app.sink]),
-                            Row::new(vec!["status (<SPACE>):", status]),
-                            Row::new(vec!["mode (⏎):", mode]),
-                            Row::new(vec!["size (+/-):", &font_size]),
-                            Row::new(vec!["bit depth (⬅/➡):", &nbits]),
-                            Row::new(vec!["charset:", &chars]),
-                        ])
-                        .block(Block::default().title(Span::styled(
-                            "Parameters (Controls)",
-                            Style::default().add_modifier(Modifier::BOLD),
-                        )))
-                        .widths(&[Constraint::Length(17), Constraint::Length(64)]);
-
-                        frame.render_widget(params, size);
-                    })
+                    .draw(|frame| draw(frame, &app, preview.as_ref()))
                     .context("Failed to write to terminal")?;
                 app.redraw = false;
             }
@@ -323,6 +999,7 @@ fn main() -> anyhow::Result<()> {
             LeaveAlternateScreen
         )?;
     }
+    app.stream.finish_recordings()?;
 
     Ok(())
 }
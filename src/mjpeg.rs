@@ -0,0 +1,46 @@
+use anyhow::{anyhow, Context};
+use jpeg_decoder::{Decoder, PixelFormat as JpegFormat};
+
+use crate::pixfmt::rgb_to_yuv;
+
+/// Decode a single `width` x `height` MJPEG frame into `buf` as YUYV.
+pub fn decode(data: &[u8], buf: &mut [u8], width: u32, height: u32) -> anyhow::Result<()> {
+    let mut decoder = Decoder::new(data);
+    let pixels = decoder.decode().context("Failed to decode MJPEG frame")?;
+    let info = decoder.info().context("Missing MJPEG frame info")?;
+    if (u32::from(info.width), u32::from(info.height)) != (width, height) {
+        return Err(anyhow!(
+            "MJPEG frame is {}x{}, expected {width}x{height}",
+            info.width,
+            info.height
+        ));
+    }
+
+    let bpp = info.pixel_format.pixel_bytes();
+    let to_yuv = match info.pixel_format {
+        JpegFormat::L8 => |px: &[u8]| rgb_to_yuv(px[0], px[0], px[0]),
+        JpegFormat::RGB24 => |px: &[u8]| rgb_to_yuv(px[0], px[1], px[2]),
+        fmt @ (JpegFormat::L16 | JpegFormat::CMYK32) => {
+            return Err(anyhow!("Unsupported MJPEG pixel format: {fmt:?}"));
+        }
+    };
+    let (src_stride, dst_stride) = (bpp * width as usize, 2 * width as usize);
+    for (src_row, dst_row) in pixels
+        .chunks_exact(src_stride)
+        .zip(buf.chunks_exact_mut(dst_stride))
+    {
+        // Each pair of pixels shares the average of their chroma
+        for (src, dst) in src_row.chunks(2 * bpp).zip(dst_row.chunks_mut(4)) {
+            let (luma1, u1, v1) = to_yuv(&src[..bpp]);
+            if src.len() < 2 * bpp {
+                // The last pixel of an odd width row has no V sample
+                dst.copy_from_slice(&[luma1, u1]);
+                continue;
+            }
+            let (luma2, u2, v2) = to_yuv(&src[bpp..]);
+            let (u, v) = (u8::midpoint(u1, u2), u8::midpoint(v1, v2));
+            dst.copy_from_slice(&[luma1, u, luma2, v]);
+        }
+    }
+    Ok(())
+}
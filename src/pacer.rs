@@ -0,0 +1,34 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use v4l::Fraction;
+
+/// Paces a `FrameSource` that isn't driven by hardware to its frame rate.
+#[derive(Debug, Clone, Copy)]
+pub struct Pacer {
+    interval: Duration,
+    deadline: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(interval: Fraction) -> Self {
+        Self {
+            interval: Duration::from_secs(interval.numerator.into()) / interval.denominator,
+            deadline: None,
+        }
+    }
+
+    /// Sleep until the next frame is due.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        let deadline = match self.deadline {
+            // Don't try to catch up if we've fallen behind
+            Some(deadline) if deadline > now => {
+                thread::sleep(deadline - now);
+                deadline
+            }
+            _ => now,
+        };
+        self.deadline = Some(deadline + self.interval);
+    }
+}
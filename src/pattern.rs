@@ -0,0 +1,257 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use v4l::Fraction;
+
+use crate::pacer::Pacer;
+use crate::{Frame, FrameSource, PixelFormat};
+
+const CHECKER_SIZE: u32 = 32;
+const NOISE_SEED: u32 = 0x9e37_79b9;
+
+// 75% SMPTE color bars
+// https://en.wikipedia.org/wiki/SMPTE_color_bars
+const BARS: [(u8, u8, u8); 7] = [
+    (191, 191, 191),
+    (191, 191, 0),
+    (0, 191, 191),
+    (0, 191, 0),
+    (191, 0, 191),
+    (191, 0, 0),
+    (0, 0, 191),
+];
+const BLACK: (u8, u8, u8) = (0, 0, 0);
+const WHITE: (u8, u8, u8) = (255, 255, 255);
+const MINUS_I: (u8, u8, u8) = (0, 33, 76);
+const PLUS_Q: (u8, u8, u8) = (50, 0, 106);
+const PLUGE: (u8, u8, u8) = (10, 10, 10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// SMPTE color bars.
+    ColorBars,
+    /// Gray, red, green and blue ramps.
+    Gradient,
+    /// A checkerboard moving diagonally by one pixel per frame.
+    Checkerboard,
+    /// A circular zone plate sweeping up to the Nyquist frequency, with its
+    /// phase shifting every frame.
+    ZonePlate,
+    /// Uniform color noise.
+    Noise,
+}
+
+impl Pattern {
+    pub const ALL: [Self; 5] = [
+        Self::ColorBars,
+        Self::Gradient,
+        Self::Checkerboard,
+        Self::ZonePlate,
+        Self::Noise,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ColorBars => "bars",
+            Self::Gradient => "gradient",
+            Self::Checkerboard => "checkerboard",
+            Self::ZonePlate => "zoneplate",
+            Self::Noise => "noise",
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|pattern| pattern.name() == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown pattern {name}, expected one of: {}",
+                    Self::ALL.map(Self::name).join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Generates a test `Pattern` at any size, `PixelFormat` and frame rate.
+#[derive(Debug)]
+pub struct PatternSource {
+    pattern: Pattern,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    interval: Fraction,
+    pacer: Pacer,
+    frame: u32,
+    rng: u32,
+}
+
+impl PatternSource {
+    /// Generate YUYV frames at 30 fps.
+    #[must_use]
+    pub fn new(pattern: Pattern, width: u32, height: u32) -> Self {
+        let interval = Fraction::new(1, 30);
+        Self {
+            pattern,
+            width,
+            height,
+            format: PixelFormat::Yuyv,
+            interval,
+            pacer: Pacer::new(interval),
+            frame: 0,
+            rng: NOISE_SEED,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_format(mut self, format: PixelFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    #[must_use]
+    pub fn with_interval(mut self, interval: Fraction) -> Self {
+        self.interval = interval;
+        self.pacer = Pacer::new(interval);
+        self
+    }
+
+    #[must_use]
+    pub const fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    fn render(&mut self, frame: &mut Frame<'_>) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let rgb = match self.pattern {
+                    Pattern::ColorBars => self.color_bars(x, y),
+                    Pattern::Gradient => self.gradient(x, y),
+                    Pattern::Checkerboard => self.checkerboard(x, y),
+                    Pattern::ZonePlate => self.zone_plate(x, y),
+                    Pattern::Noise => self.noise(),
+                };
+                frame.set_rgb(x, y, rgb);
+            }
+        }
+    }
+
+    /// Seven bars over the top two thirds, reversed blue bars below them, and
+    /// -I, white, +Q and a PLUGE along the bottom quarter.
+    const fn color_bars(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        // Position in twelfths of a bar width
+        let pos = 7 * 12 * x / self.width;
+        let bar = (pos / 12) as usize;
+        if 3 * y < 2 * self.height {
+            BARS[bar]
+        } else if 4 * y < 3 * self.height {
+            match bar {
+                0 | 2 | 4 | 6 => BARS[6 - bar],
+                _ => BLACK,
+            }
+        } else {
+            match pos {
+                0..15 => MINUS_I,
+                15..30 => WHITE,
+                30..45 => PLUS_Q,
+                68..72 => PLUGE,
+                _ => BLACK,
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn gradient(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let c = (255 * x / self.width.saturating_sub(1).max(1)) as u8;
+        match 4 * y / self.height {
+            0 => (c, c, c),
+            1 => (c, 0, 0),
+            2 => (0, c, 0),
+            _ => (0, 0, c),
+        }
+    }
+
+    const fn checkerboard(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let (x, y) = (x.wrapping_add(self.frame), y.wrapping_add(self.frame));
+        if (x / CHECKER_SIZE + y / CHECKER_SIZE).is_multiple_of(2) {
+            WHITE
+        } else {
+            BLACK
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    fn zone_plate(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let dx = x as f32 - self.width as f32 / 2.0;
+        let dy = y as f32 - self.height as f32 / 2.0;
+        let radius = (self.width.max(self.height) as f32 / 2.0).max(1.0);
+        // The local frequency, k * r / pi, reaches 0.5 cycles per pixel at
+        // the edge
+        let k = PI / (2.0 * radius);
+        let phase = k * (dx * dx + dy * dy) + self.frame as f32 * PI / 16.0;
+        let c = (127.5 * (1.0 + phase.cos())) as u8;
+        (c, c, c)
+    }
+
+    /// xorshift32
+    const fn noise(&mut self) -> (u8, u8, u8) {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        let [red, green, blue, _] = self.rng.to_le_bytes();
+        (red, green, blue)
+    }
+}
+
+impl FrameSource for PatternSource {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn interval(&self) -> Fraction {
+        self.interval
+    }
+
+    fn read_frame(&mut self, buf: &mut [u8]) -> anyhow::Result<bool> {
+        self.pacer.wait();
+        let mut frame = Frame::with_format(buf, self.width, self.height, self.format);
+        self.render(&mut frame);
+        self.frame = self.frame.wrapping_add(1);
+        Ok(true)
+    }
+}
+
+impl fmt::Display for PatternSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "pattern        : {}", self.pattern)?;
+        writeln!(f, "width          : {}", self.width)?;
+        writeln!(f, "height         : {}", self.height)?;
+        writeln!(f, "fourcc         : {}", self.format)?;
+        writeln!(f, "interval       : {} [s]", self.interval)
+    }
+}
@@ -0,0 +1,730 @@
+use std::fmt;
+
+use anyhow::anyhow;
+use v4l::format::fourcc::FourCC;
+
+use crate::Brightness;
+
+pub(crate) const GRAY_CHROMA: u8 = 127;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Yuyv,
+    Nv12,
+    Nv21,
+    I420,
+    Yv12,
+    Rgb24,
+    Bgr24,
+    Rgba32,
+    Xrgb32,
+}
+
+impl PixelFormat {
+    /// Supported formats in order of preference when negotiating with a
+    /// device.
+    pub const PREFERRED: [Self; 9] = [
+        Self::Yuyv,
+        Self::Nv12,
+        Self::Nv21,
+        Self::I420,
+        Self::Yv12,
+        Self::Rgb24,
+        Self::Bgr24,
+        Self::Rgba32,
+        Self::Xrgb32,
+    ];
+
+    #[must_use]
+    pub fn fourcc(self) -> FourCC {
+        FourCC::new(match self {
+            Self::Yuyv => b"YUYV",
+            Self::Nv12 => b"NV12",
+            Self::Nv21 => b"NV21",
+            Self::I420 => b"YU12",
+            Self::Yv12 => b"YV12",
+            Self::Rgb24 => b"RGB3",
+            Self::Bgr24 => b"BGR3",
+            Self::Rgba32 => b"AB24",
+            Self::Xrgb32 => b"BX24",
+        })
+    }
+
+    #[must_use]
+    pub const fn is_rgb(self) -> bool {
+        self.rgb_layout().is_some()
+    }
+
+    /// Bytes per pixel and the offsets of the red, green, and blue bytes.
+    const fn rgb_layout(self) -> Option<RgbLayout> {
+        match self {
+            Self::Yuyv | Self::Nv12 | Self::Nv21 | Self::I420 | Self::Yv12 => None,
+            Self::Rgb24 => Some(RgbLayout::new(3, [0, 1, 2])),
+            Self::Bgr24 => Some(RgbLayout::new(3, [2, 1, 0])),
+            Self::Rgba32 => Some(RgbLayout::new(4, [0, 1, 2])),
+            Self::Xrgb32 => Some(RgbLayout::new(4, [1, 2, 3])),
+        }
+    }
+
+    /// The number of bytes in a `width` x `height` frame.
+    #[must_use]
+    pub fn frame_size(self, width: u32, height: u32) -> usize {
+        self.planes(width, height)
+            .iter()
+            .map(PlaneLayout::len)
+            .sum()
+    }
+
+    /// Vertical chroma subsampling factor.
+    const fn vsub(self) -> u32 {
+        match self {
+            Self::Yuyv | Self::Rgb24 | Self::Bgr24 | Self::Rgba32 | Self::Xrgb32 => 1,
+            Self::Nv12 | Self::Nv21 | Self::I420 | Self::Yv12 => 2,
+        }
+    }
+
+    fn planes(self, width: u32, height: u32) -> Vec<PlaneLayout> {
+        let chroma_width = width.div_ceil(2);
+        let chroma_height = height.div_ceil(self.vsub());
+        match self {
+            // https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/pixfmt-packed-yuv.html
+            Self::Yuyv => vec![PlaneLayout::new(2 * width, height, 1)],
+            // https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/pixfmt-yuv-planar.html
+            Self::Nv12 | Self::Nv21 => vec![
+                PlaneLayout::new(width, height, 1),
+                PlaneLayout::new(2 * chroma_width, chroma_height, 2),
+            ],
+            Self::I420 | Self::Yv12 => vec![
+                PlaneLayout::new(width, height, 1),
+                PlaneLayout::new(chroma_width, chroma_height, 2),
+                PlaneLayout::new(chroma_width, chroma_height, 2),
+            ],
+            // https://www.kernel.org/doc/html/latest/userspace-api/media/v4l/pixfmt-rgb.html
+            Self::Rgb24 | Self::Bgr24 | Self::Rgba32 | Self::Xrgb32 => {
+                let bpp = self.rgb_layout().unwrap().bpp;
+                vec![PlaneLayout::new(bpp * width, height, 1)]
+            }
+        }
+    }
+}
+
+impl TryFrom<FourCC> for PixelFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(fourcc: FourCC) -> Result<Self, Self::Error> {
+        Self::PREFERRED
+            .into_iter()
+            .find(|fmt| fmt.fourcc() == fourcc)
+            .ok_or_else(|| anyhow!("Unsupported fourcc: {fourcc}"))
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fourcc())
+    }
+}
+
+/// The format frames are captured in. Compressed formats are decoded to a
+/// `PixelFormat` before filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Raw(PixelFormat),
+    Mjpeg,
+}
+
+impl CaptureFormat {
+    /// Supported formats in order of preference when negotiating with a
+    /// device. Raw formats are preferred unless MJPEG allows a higher frame
+    /// rate.
+    pub const PREFERRED: [Self; 10] = [
+        Self::Raw(PixelFormat::Yuyv),
+        Self::Raw(PixelFormat::Nv12),
+        Self::Raw(PixelFormat::Nv21),
+        Self::Raw(PixelFormat::I420),
+        Self::Raw(PixelFormat::Yv12),
+        Self::Raw(PixelFormat::Rgb24),
+        Self::Raw(PixelFormat::Bgr24),
+        Self::Raw(PixelFormat::Rgba32),
+        Self::Raw(PixelFormat::Xrgb32),
+        Self::Mjpeg,
+    ];
+
+    #[must_use]
+    pub fn fourcc(self) -> FourCC {
+        match self {
+            Self::Raw(fmt) => fmt.fourcc(),
+            Self::Mjpeg => FourCC::new(b"MJPG"),
+        }
+    }
+}
+
+impl TryFrom<FourCC> for CaptureFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(fourcc: FourCC) -> Result<Self, Self::Error> {
+        Self::PREFERRED
+            .into_iter()
+            .find(|fmt| fmt.fourcc() == fourcc)
+            .ok_or_else(|| anyhow!("Unsupported fourcc: {fourcc}"))
+    }
+}
+
+impl fmt::Display for CaptureFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fourcc())
+    }
+}
+
+/// Convert from limited range BT.601 YUV.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+#[must_use]
+pub const fn yuv_to_rgb(luma: u8, u: u8, v: u8) -> (u8, u8, u8) {
+    let luma = 298 * (luma as i32 - 16);
+    let (u, v) = (u as i32 - 128, v as i32 - 128);
+    (
+        clamp_channel(luma + 409 * v + 128),
+        clamp_channel(luma - 100 * u - 208 * v + 128),
+        clamp_channel(luma + 516 * u + 128),
+    )
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn clamp_channel(c: i32) -> u8 {
+    let c = c >> 8;
+    if c < 0 {
+        0
+    } else if c > u8::MAX as i32 {
+        u8::MAX
+    } else {
+        c as u8
+    }
+}
+
+/// Convert to limited range BT.601 YUV.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+#[must_use]
+pub const fn rgb_to_yuv(red: u8, green: u8, blue: u8) -> (u8, u8, u8) {
+    let (red, green, blue) = (red as i32, green as i32, blue as i32);
+    let luma = ((66 * red + 129 * green + 25 * blue + 128) >> 8) + 16;
+    let u = ((-38 * red - 74 * green + 112 * blue + 128) >> 8) + 128;
+    let v = ((112 * red - 94 * green - 18 * blue + 128) >> 8) + 128;
+    (luma as u8, u as u8, v as u8)
+}
+
+/// The index of a pixel's luma sample, and the (plane, index) of its U and
+/// V samples.
+type YuvIdx = (usize, (usize, usize), (usize, usize));
+
+#[derive(Debug, Clone, Copy)]
+struct RgbLayout {
+    bpp: u32,
+    offsets: [usize; 3],
+}
+
+impl RgbLayout {
+    const fn new(bpp: u32, offsets: [usize; 3]) -> Self {
+        Self { bpp, offsets }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PlaneLayout {
+    stride: u32,
+    rows: u32,
+    vsub: u32,
+}
+
+impl PlaneLayout {
+    const fn new(stride: u32, rows: u32, vsub: u32) -> Self {
+        Self { stride, rows, vsub }
+    }
+
+    const fn len(&self) -> usize {
+        (self.stride * self.rows) as usize
+    }
+}
+
+/// A view of the planes of a frame in a particular `PixelFormat`.
+#[derive(Debug)]
+pub(crate) struct Pixels<'pix> {
+    planes: Vec<&'pix mut [u8]>,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+}
+
+impl<'pix> Pixels<'pix> {
+    #[must_use]
+    pub fn new(buf: &'pix mut [u8], width: u32, height: u32, format: PixelFormat) -> Self {
+        debug_assert!(
+            buf.len() >= format.frame_size(width, height),
+            "len={} format={format} width={width} height={height}",
+            buf.len()
+        );
+        let mut planes = vec![];
+        let mut rest = buf;
+        for plane in format.planes(width, height) {
+            let (plane, tail) = rest.split_at_mut(plane.len());
+            planes.push(plane);
+            rest = tail;
+        }
+        Self {
+            planes,
+            format,
+            width,
+            height,
+        }
+    }
+
+    #[must_use]
+    pub const fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The planes of the frame in memory order.
+    pub fn planes(&self) -> impl Iterator<Item = &[u8]> {
+        self.planes.iter().map(|plane| &**plane)
+    }
+
+    /// Copy the planes of `src`, which must have the same size and format.
+    pub fn copy_from(&mut self, src: &Pixels<'_>) {
+        for (dst, src) in self.planes.iter_mut().zip(src.planes()) {
+            dst.copy_from_slice(src);
+        }
+    }
+
+    /// Split into about `2^n` horizontal bands. Bands are rounded up to a
+    /// whole number of chroma rows, so the last one may be shorter.
+    #[must_use]
+    pub fn splitn(&mut self, n: u32) -> Vec<Pixels<'_>> {
+        debug_assert!(n > 0);
+        self.split_rows(self.height >> n)
+    }
+
+    /// Split into horizontal bands of `rows` rows, rounded up to a whole
+    /// number of chroma rows. The last band may be shorter.
+    #[must_use]
+    pub fn split_rows(&mut self, rows: u32) -> Vec<Pixels<'_>> {
+        let rows = rows.max(1).next_multiple_of(self.format.vsub());
+        let layouts = self.format.planes(self.width, self.height);
+
+        let mut chunks = self
+            .planes
+            .iter_mut()
+            .zip(&layouts)
+            .map(|(plane, layout)| {
+                let len = (layout.stride * rows / layout.vsub) as usize;
+                plane.chunks_mut(len)
+            })
+            .collect::<Vec<_>>();
+
+        let mut subs = vec![];
+        let mut y = 0;
+        while y < self.height {
+            let planes = chunks
+                .iter_mut()
+                .map(|chunk| chunk.next().unwrap())
+                .collect();
+            let height = rows.min(self.height - y);
+            subs.push(Pixels {
+                planes,
+                format: self.format,
+                width: self.width,
+                height,
+            });
+            y += height;
+        }
+        debug_assert!(chunks.iter_mut().all(|chunk| chunk.next().is_none()));
+        subs
+    }
+
+    /// The average brightness of the `width` x `height` block at (`x`, `y`),
+    /// clipped to the frame.
+    #[allow(clippy::cast_possible_truncation)]
+    #[must_use]
+    pub fn avg_brightness(&self, x: u32, y: u32, width: u32, height: u32) -> Brightness {
+        let xs = x..(x + width).min(self.width);
+        let ys = y..(y + height).min(self.height);
+        let npix = (xs.len() * ys.len()).max(1) as u32;
+        let sum = ys
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| u32::from(self.get_brightness(x, y).0))
+            .sum::<u32>();
+        Brightness((sum / npix) as u8)
+    }
+
+    /// The average color of the `width` x `height` block at (`x`, `y`),
+    /// clipped to the frame.
+    #[allow(clippy::cast_possible_truncation)]
+    #[must_use]
+    pub fn avg_rgb(&self, x: u32, y: u32, width: u32, height: u32) -> (u8, u8, u8) {
+        let xs = x..(x + width).min(self.width);
+        let ys = y..(y + height).min(self.height);
+        let npix = (xs.len() * ys.len()).max(1) as u32;
+        let (red, green, blue) = ys
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| self.get_rgb(x, y))
+            .fold((0, 0, 0), |(r, g, b), (red, green, blue)| {
+                (
+                    r + u32::from(red),
+                    g + u32::from(green),
+                    b + u32::from(blue),
+                )
+            });
+        (
+            (red / npix) as u8,
+            (green / npix) as u8,
+            (blue / npix) as u8,
+        )
+    }
+
+    #[must_use]
+    pub fn get_brightness(&self, x: u32, y: u32) -> Brightness {
+        match self.yuv_idx(x, y) {
+            Some((luma, _, _)) => Brightness(self.planes[0][luma]),
+            None => Brightness(self.get_yuv(x, y).0),
+        }
+    }
+
+    /// Set the luma of the pixel at (`x`, `y`) without changing its chroma.
+    pub fn set_brightness<B>(&mut self, x: u32, y: u32, brightness: B)
+    where
+        B: Into<Brightness>,
+    {
+        let luma = brightness.into().0;
+        if let Some((idx, _, _)) = self.yuv_idx(x, y) {
+            self.planes[0][idx] = luma;
+        } else {
+            let (_, u, v) = self.get_yuv(x, y);
+            self.set_yuv(x, y, (luma, u, v));
+        }
+    }
+
+    #[must_use]
+    pub fn get_yuv(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        if let Some((luma, (u_plane, u_idx), (v_plane, v_idx))) = self.yuv_idx(x, y) {
+            (
+                self.planes[0][luma],
+                self.planes[u_plane][u_idx],
+                self.planes[v_plane][v_idx],
+            )
+        } else {
+            let (red, green, blue) = self.get_rgb(x, y);
+            rgb_to_yuv(red, green, blue)
+        }
+    }
+
+    /// Set the luma of the pixel at (`x`, `y`) and the chroma of the block
+    /// it shares with its neighbors.
+    pub fn set_yuv(&mut self, x: u32, y: u32, (luma, u, v): (u8, u8, u8)) {
+        if let Some((idx, (u_plane, u_idx), (v_plane, v_idx))) = self.yuv_idx(x, y) {
+            self.planes[0][idx] = luma;
+            self.planes[u_plane][u_idx] = u;
+            self.planes[v_plane][v_idx] = v;
+        } else {
+            self.set_rgb(x, y, yuv_to_rgb(luma, u, v));
+        }
+    }
+
+    #[must_use]
+    pub fn get_rgb(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        if let Some(layout) = self.format.rgb_layout() {
+            let idx = self.rgb_idx(layout, x, y);
+            let [red, green, blue] = layout.offsets.map(|off| self.planes[0][idx + off]);
+            (red, green, blue)
+        } else {
+            let (luma, u, v) = self.get_yuv(x, y);
+            yuv_to_rgb(luma, u, v)
+        }
+    }
+
+    pub fn set_rgb(&mut self, x: u32, y: u32, (red, green, blue): (u8, u8, u8)) {
+        if let Some(layout) = self.format.rgb_layout() {
+            let idx = self.rgb_idx(layout, x, y);
+            for (off, c) in layout.offsets.into_iter().zip([red, green, blue]) {
+                self.planes[0][idx + off] = c;
+            }
+        } else {
+            self.set_yuv(x, y, rgb_to_yuv(red, green, blue));
+        }
+    }
+
+    /// Make the chroma of every pixel neutral, keeping its luma.
+    pub fn as_grayscale(&mut self) {
+        match self.format {
+            PixelFormat::Yuyv => {
+                for chroma in self.planes[0].iter_mut().skip(1).step_by(2) {
+                    *chroma = GRAY_CHROMA;
+                }
+            }
+            PixelFormat::Nv12 | PixelFormat::Nv21 => self.planes[1].fill(GRAY_CHROMA),
+            PixelFormat::I420 | PixelFormat::Yv12 => {
+                self.planes[1].fill(GRAY_CHROMA);
+                self.planes[2].fill(GRAY_CHROMA);
+            }
+            PixelFormat::Rgb24 | PixelFormat::Bgr24 | PixelFormat::Rgba32 | PixelFormat::Xrgb32 => {
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let luma = self.get_brightness(x, y).0;
+                        self.set_yuv(x, y, (luma, GRAY_CHROMA, GRAY_CHROMA));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The index in the first plane of the luma sample of the pixel at
+    /// (`x`, `y`), and the (plane, index) of its U and V samples, unless the
+    /// format is RGB.
+    //
+    // YUYV:
+    // 0       1       2       3
+    // Y1 U1/2 Y2 V1/2 Y3 U3/4 Y4 V3/4      0
+    // Y5 U5/6 Y6 V5/6 Y7 U7/8 Y8 V7/8      1
+    //
+    // NV12/NV21:
+    // Y1 Y2 Y3 Y4      0
+    // Y5 Y6 Y7 Y8      1
+    // U1 V1 U2 V2      (NV21 swaps U and V)
+    //
+    // I420/YV12:
+    // Y1 Y2 Y3 Y4      0
+    // Y5 Y6 Y7 Y8      1
+    // U1 U2
+    // V1 V2            (YV12 swaps the U and V planes)
+    #[must_use]
+    const fn yuv_idx(&self, x: u32, y: u32) -> Option<YuvIdx> {
+        let luma = (y * self.width + x) as usize;
+        let chroma_width = self.width.div_ceil(2);
+        let semi_planar = (2 * ((y / 2) * chroma_width + x / 2)) as usize;
+        let planar = ((y / 2) * chroma_width + x / 2) as usize;
+        match self.format {
+            PixelFormat::Yuyv => {
+                let idx = (2 * (y * self.width + x - x % 2)) as usize;
+                // The last pixel of an odd width row has no pair to share a
+                // V sample with, so its U sample stands in for it
+                let v_idx = if x - x % 2 + 1 < self.width {
+                    idx + 3
+                } else {
+                    idx + 1
+                };
+                Some((2 * luma, (0, idx + 1), (0, v_idx)))
+            }
+            PixelFormat::Nv12 => Some((luma, (1, semi_planar), (1, semi_planar + 1))),
+            PixelFormat::Nv21 => Some((luma, (1, semi_planar + 1), (1, semi_planar))),
+            PixelFormat::I420 => Some((luma, (1, planar), (2, planar))),
+            PixelFormat::Yv12 => Some((luma, (2, planar), (1, planar))),
+            PixelFormat::Rgb24 | PixelFormat::Bgr24 | PixelFormat::Rgba32 | PixelFormat::Xrgb32 => {
+                None
+            }
+        }
+    }
+
+    // RGB24:  R1 G1 B1 R2 G2 B2
+    // BGR24:  B1 G1 R1 B2 G2 R2
+    // RGBA32: R1 G1 B1 A1 R2 G2 B2 A2
+    // XRGB32: X1 R1 G1 B1 X2 R2 G2 B2
+    #[must_use]
+    const fn rgb_idx(&self, layout: RgbLayout, x: u32, y: u32) -> usize {
+        (layout.bpp * (y * self.width + x)) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set every pixel of a `width` x `height` frame in `format` to its own
+    /// YUV color, checking that it reads back the same and that the pixels
+    /// sharing its chroma follow it.
+    fn assert_yuv_round_trip(format: PixelFormat, width: u32, height: u32) {
+        let mut buf = vec![0; format.frame_size(width, height)];
+        let mut pixels = Pixels::new(&mut buf, width, height, format);
+        let vsub = format.vsub();
+        for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+            let yuv = yuv(x, y);
+            pixels.set_yuv(x, y, yuv);
+            assert_eq!(pixels.get_yuv(x, y), yuv, "{format} ({x}, {y})");
+            let (x0, y0) = (x - x % 2, y - y % vsub);
+            for (nx, ny) in [(x0, y0), (x0 + 1, y0), (x0, y0 + 1), (x0 + 1, y0 + 1)] {
+                if nx < width && ny < y0 + vsub && ny < height {
+                    let (_, u, v) = pixels.get_yuv(nx, ny);
+                    assert_eq!((u, v), (yuv.1, yuv.2), "{format} ({nx}, {ny})");
+                }
+            }
+        }
+        for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+            assert_eq!(
+                pixels.get_brightness(x, y).0,
+                yuv(x, y).0,
+                "{format} ({x}, {y})"
+            );
+        }
+    }
+
+    /// The YUV color of each pixel, row by row.
+    fn yuv_values(pixels: &Pixels<'_>) -> Vec<(u8, u8, u8)> {
+        (0..pixels.height())
+            .flat_map(|y| (0..pixels.width()).map(move |x| pixels.get_yuv(x, y)))
+            .collect()
+    }
+
+    /// A distinct color for each pixel of a small frame.
+    #[allow(clippy::cast_possible_truncation)]
+    const fn yuv(x: u32, y: u32) -> (u8, u8, u8) {
+        let n = (y * 16 + x) as u8;
+        (16 + n, 64 + n, 192 - n)
+    }
+
+    /// The bytes of a `width` x `height` frame in `format` after setting the
+    /// pixel at (1, 1) to `yuv`.
+    fn bytes_after_set(
+        format: PixelFormat,
+        (width, height): (u32, u32),
+        yuv: (u8, u8, u8),
+    ) -> Vec<u8> {
+        let mut buf = vec![0; format.frame_size(width, height)];
+        Pixels::new(&mut buf, width, height, format).set_yuv(1, 1, yuv);
+        buf
+    }
+
+    #[test]
+    fn yuyv_round_trip() {
+        assert_yuv_round_trip(PixelFormat::Yuyv, 4, 3);
+        let buf = bytes_after_set(PixelFormat::Yuyv, (4, 2), (1, 2, 3));
+        assert_eq!(buf[8..16], [0, 2, 1, 3, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn semi_planar_round_trip() {
+        for format in [PixelFormat::Nv12, PixelFormat::Nv21] {
+            assert_yuv_round_trip(format, 4, 4);
+            assert_yuv_round_trip(format, 5, 3);
+        }
+        let nv12 = bytes_after_set(PixelFormat::Nv12, (4, 2), (1, 2, 3));
+        assert_eq!(nv12, [0, 0, 0, 0, 0, 1, 0, 0, 2, 3, 0, 0]);
+        let nv21 = bytes_after_set(PixelFormat::Nv21, (4, 2), (1, 2, 3));
+        assert_eq!(nv21, [0, 0, 0, 0, 0, 1, 0, 0, 3, 2, 0, 0]);
+    }
+
+    #[test]
+    fn planar_round_trip() {
+        for format in [PixelFormat::I420, PixelFormat::Yv12] {
+            assert_yuv_round_trip(format, 4, 4);
+            assert_yuv_round_trip(format, 5, 3);
+        }
+        let i420 = bytes_after_set(PixelFormat::I420, (4, 2), (1, 2, 3));
+        assert_eq!(i420, [0, 0, 0, 0, 0, 1, 0, 0, 2, 0, 3, 0]);
+        let yv12 = bytes_after_set(PixelFormat::Yv12, (4, 2), (1, 2, 3));
+        assert_eq!(yv12, [0, 0, 0, 0, 0, 1, 0, 0, 3, 0, 2, 0]);
+    }
+
+    #[test]
+    fn split_rows_rounds_to_chroma_rows() {
+        let (width, height) = (4, 5);
+        for format in [PixelFormat::Yuyv, PixelFormat::Nv12, PixelFormat::I420] {
+            let mut buf = vec![0; format.frame_size(width, height)];
+            let mut pixels = Pixels::new(&mut buf, width, height, format);
+            for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+                pixels.set_yuv(x, y, yuv(x, y));
+            }
+            let expected = yuv_values(&pixels);
+
+            let bands = pixels.split_rows(1);
+            let heights = bands.iter().map(Pixels::height).collect::<Vec<_>>();
+            let band_height = format.vsub();
+            assert!(heights[..heights.len() - 1]
+                .iter()
+                .all(|&h| h == band_height));
+            assert_eq!(heights.iter().sum::<u32>(), height, "{format}");
+            let actual = bands.iter().flat_map(yuv_values).collect::<Vec<_>>();
+            assert_eq!(actual, expected, "{format}");
+        }
+    }
+
+    #[test]
+    fn yuyv_odd_width() {
+        let (width, height) = (3, 2);
+        let format = PixelFormat::Yuyv;
+        let mut buf = vec![0; format.frame_size(width, height)];
+        let mut pixels = Pixels::new(&mut buf, width, height, format);
+        for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+            pixels.set_yuv(x, y, yuv(x, y));
+        }
+        for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+            // Pairs keep the chroma set last, the second pixel's
+            let (luma, _, _) = yuv(x, y);
+            let (_, u, v) = yuv((x | 1).min(width - 1), y);
+            let expected = if x + 1 == width {
+                (luma, v, v)
+            } else {
+                (luma, u, v)
+            };
+            assert_eq!(pixels.get_yuv(x, y), expected, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn rgb_round_trip() {
+        for (format, bytes) in [
+            (PixelFormat::Rgb24, &[10, 20, 30][..]),
+            (PixelFormat::Bgr24, &[30, 20, 10]),
+            (PixelFormat::Rgba32, &[10, 20, 30, 0]),
+            (PixelFormat::Xrgb32, &[0, 10, 20, 30]),
+        ] {
+            let (width, height) = (3, 2);
+            let mut buf = vec![0; format.frame_size(width, height)];
+            let mut pixels = Pixels::new(&mut buf, width, height, format);
+            for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+                pixels.set_rgb(x, y, yuv(x, y));
+            }
+            for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+                assert_eq!(pixels.get_rgb(x, y), yuv(x, y), "{format} ({x}, {y})");
+            }
+
+            let mut buf = vec![0; format.frame_size(width, height)];
+            Pixels::new(&mut buf, width, height, format).set_rgb(1, 1, (10, 20, 30));
+            let bpp = bytes.len();
+            let idx = bpp * (width as usize + 1);
+            assert_eq!(&buf[idx..idx + bpp], bytes, "{format}");
+            assert_eq!(buf.iter().filter(|&&b| b != 0).count(), 3, "{format}");
+        }
+    }
+
+    #[test]
+    fn as_grayscale_keeps_luma() {
+        for format in [
+            PixelFormat::Yuyv,
+            PixelFormat::Nv12,
+            PixelFormat::Nv21,
+            PixelFormat::I420,
+            PixelFormat::Yv12,
+        ] {
+            let mut buf = vec![0; format.frame_size(4, 2)];
+            let mut pixels = Pixels::new(&mut buf, 4, 2, format);
+            pixels.set_yuv(1, 1, (100, 20, 230));
+            pixels.as_grayscale();
+            assert_eq!(
+                pixels.get_yuv(1, 1),
+                (100, GRAY_CHROMA, GRAY_CHROMA),
+                "{format}"
+            );
+            assert_eq!(
+                pixels.get_yuv(2, 0),
+                (0, GRAY_CHROMA, GRAY_CHROMA),
+                "{format}"
+            );
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use std::cmp::Reverse;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, IntoInnerError, Read, Seek, SeekFrom, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use v4l::Fraction;
+
+use crate::{Frame, PixelFormat};
+
+/// Bits per channel of the colors the palette lookup table tells apart.
+const LUT_BITS: u32 = 6;
+const MAX_COLORS: usize = 256;
+/// Frames waiting to be encoded before more are skipped.
+const ENCODER_QUEUE: usize = 4;
+/// The polynomial of the CRC-32 ending PNG chunks, reversed.
+const PNG_CRC: u32 = 0xedb8_8320;
+
+/// An animated image format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    /// Pick the format from the extension of `path`, .gif or .png/.apng.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gif" => Some(Self::Gif),
+            "png" | "apng" => Some(Self::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// The colors of a frame of a recording.
+///
+/// ASCII art is mostly a flat background and a handful of glyph shades, so
+/// the most common colors are kept as they are, and the rest are mapped to
+/// the closest of those.
+#[derive(Debug)]
+struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+    lut: Vec<Option<u8>>,
+}
+
+impl Palette {
+    #[allow(clippy::cast_possible_truncation)]
+    fn new(rgb: &[u8]) -> Self {
+        // Pixel count and channel sums per bucket
+        let mut buckets = vec![(0_u32, [0_u32; 3]); 1 << (3 * LUT_BITS)];
+        for pix in rgb.chunks_exact(3) {
+            let (count, sums) = &mut buckets[bucket((pix[0], pix[1], pix[2]))];
+            *count += 1;
+            for (sum, c) in sums.iter_mut().zip(pix) {
+                *sum += u32::from(*c);
+            }
+        }
+        let mut used = buckets
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .collect::<Vec<_>>();
+        used.sort_unstable_by_key(|(count, _)| Reverse(*count));
+        let colors = used
+            .iter()
+            .take(MAX_COLORS)
+            .map(|(count, [red, green, blue])| {
+                (
+                    (red / count) as u8,
+                    (green / count) as u8,
+                    (blue / count) as u8,
+                )
+            })
+            .collect();
+        Self {
+            colors,
+            lut: vec![None; 1 << (3 * LUT_BITS)],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn index(&mut self, rgb: (u8, u8, u8)) -> u8 {
+        let colors = &self.colors;
+        *self.lut[bucket(rgb)].get_or_insert_with(|| {
+            let dist = |(red, green, blue): (u8, u8, u8)| {
+                let (dr, dg, db) = (
+                    i32::from(red) - i32::from(rgb.0),
+                    i32::from(green) - i32::from(rgb.1),
+                    i32::from(blue) - i32::from(rgb.2),
+                );
+                dr * dr + dg * dg + db * db
+            };
+            (0..colors.len())
+                .min_by_key(|&idx| dist(colors[idx]))
+                .unwrap_or_default() as u8
+        })
+    }
+
+    /// The palette as RGB triples.
+    fn to_bytes(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|&(red, green, blue)| [red, green, blue])
+            .collect()
+    }
+}
+
+const fn bucket((red, green, blue): (u8, u8, u8)) -> usize {
+    let shift = u8::BITS - LUT_BITS;
+    ((red as usize >> shift) << (2 * LUT_BITS))
+        | ((green as usize >> shift) << LUT_BITS)
+        | (blue as usize >> shift)
+}
+
+/// A frame to encode, with the number of frame intervals since the one
+/// before it.
+type Message = (Vec<u8>, u32);
+
+/// Records frames and encodes them as an animated GIF or APNG.
+///
+/// Frames are encoded on a worker thread as they are recorded. When it falls
+/// behind, frames are skipped and the one before shown for longer.
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    format: AnimationFormat,
+    width: u32,
+    height: u32,
+    interval: Fraction,
+    max_frames: Option<usize>,
+    /// The frames recorded, including skipped ones.
+    len: usize,
+    /// The frame intervals since the last frame sent to the encoder.
+    intervals: u32,
+    /// The last frame skipped since then, sent when stopping so that the
+    /// recording ends on it.
+    skipped: Option<Vec<u8>>,
+    /// The file, until the encoder takes it with the first frame.
+    writer: Option<BufWriter<File>>,
+    encoder: Option<(SyncSender<Message>, JoinHandle<anyhow::Result<()>>)>,
+}
+
+impl Recorder {
+    /// Record `width` x `height` frames shown for `interval` seconds each
+    /// to `path`, in the format given by its extension.
+    pub fn new<P>(path: P, width: u32, height: u32, interval: Fraction) -> anyhow::Result<Self>
+    where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        let format = AnimationFormat::from_path(&path).with_context(|| {
+            format!(
+                "Unknown animation format {}, expected a .gif, .png or .apng file",
+                path.display()
+            )
+        })?;
+        // APNGs are read back to fill in their number of frames
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self {
+            path,
+            format,
+            width,
+            height,
+            interval,
+            max_frames: None,
+            len: 0,
+            intervals: 0,
+            skipped: None,
+            writer: Some(BufWriter::new(file)),
+            encoder: None,
+        })
+    }
+
+    /// Stop recording after `duration`.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    #[must_use]
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        let interval = f64::from(self.interval.numerator) / f64::from(self.interval.denominator);
+        self.max_frames = Some((duration.as_secs_f64() / interval).ceil().max(1.0) as usize);
+        self
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of frames recorded.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether the recording reached its duration.
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.max_frames.is_some_and(|max| self.len >= max)
+    }
+
+    /// Add `frame`, scaled to the recording size. Frames past the duration
+    /// are dropped.
+    pub fn record(&mut self, frame: &Frame<'_>) {
+        if self.is_full() {
+            return;
+        }
+        let mut rgb = vec![0; PixelFormat::Rgb24.frame_size(self.width, self.height)];
+        let mut rgb_frame =
+            Frame::with_format(&mut rgb, self.width, self.height, PixelFormat::Rgb24);
+        frame.convert_into(&mut rgb_frame);
+        self.len += 1;
+        self.intervals += 1;
+        let (frames, _) = self.encoder.get_or_insert_with(|| {
+            // The writer is only taken here
+            let writer = self.writer.take().unwrap();
+            spawn_encoder(
+                writer,
+                self.path.clone(),
+                self.format,
+                (self.width, self.height),
+                self.interval,
+            )
+        });
+        match frames.try_send((rgb, self.intervals)) {
+            Ok(()) => {
+                self.intervals = 0;
+                self.skipped = None;
+            }
+            Err(TrySendError::Full((rgb, _))) => self.skipped = Some(rgb),
+            // The encoder failed, which stopping reports
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+
+    /// Stop recording, leaving the encoder to finish writing it in the
+    /// background.
+    #[must_use]
+    pub fn stop(self) -> Encoding {
+        let encoder = self.encoder.map(|(frames, encoder)| {
+            if let Some(rgb) = self.skipped {
+                // Sending only fails if the encoder failed, which it reports
+                let _ = frames.send((rgb, self.intervals));
+            }
+            encoder
+        });
+        let error = encoder
+            .is_none()
+            .then(|| anyhow!("Nothing was recorded to {}", self.path.display()));
+        Encoding {
+            path: self.path,
+            encoder,
+            error,
+        }
+    }
+
+    /// Stop recording and wait for it to be written out.
+    pub fn finish(self) -> anyhow::Result<()> {
+        self.stop().wait()
+    }
+}
+
+/// A recording being written out after it stopped.
+#[derive(Debug)]
+pub struct Encoding {
+    path: PathBuf,
+    encoder: Option<JoinHandle<anyhow::Result<()>>>,
+    error: Option<anyhow::Error>,
+}
+
+impl Encoding {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the recording was written out, or failed to be, as of the
+    /// last `poll`.
+    #[must_use]
+    pub const fn is_done(&self) -> bool {
+        self.encoder.is_none()
+    }
+
+    /// Why the recording failed, once it is done.
+    #[must_use]
+    pub const fn error(&self) -> Option<&anyhow::Error> {
+        self.error.as_ref()
+    }
+
+    /// Collect the outcome of the encoder if it finished.
+    pub fn poll(&mut self) {
+        if let Some(encoder) = self.encoder.take_if(|encoder| encoder.is_finished()) {
+            self.error = join(encoder).err();
+        }
+    }
+
+    /// Wait for the recording to be written out.
+    pub fn wait(mut self) -> anyhow::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            join(encoder)?;
+        }
+        self.error.map_or(Ok(()), Err)
+    }
+}
+
+fn join(encoder: JoinHandle<anyhow::Result<()>>) -> anyhow::Result<()> {
+    encoder
+        .join()
+        .map_err(|_| anyhow!("The recording encoder panicked"))?
+}
+
+/// Start encoding the frames sent to the returned sender into `writer`,
+/// which is the file at `path`.
+fn spawn_encoder(
+    writer: BufWriter<File>,
+    path: PathBuf,
+    format: AnimationFormat,
+    (width, height): (u32, u32),
+    interval: Fraction,
+) -> (SyncSender<Message>, JoinHandle<anyhow::Result<()>>) {
+    let (frames, rx) = mpsc::sync_channel(ENCODER_QUEUE);
+    let encoder = thread::spawn(move || {
+        let frames = shown_frames(rx);
+        match format {
+            AnimationFormat::Gif => write_gif(writer, width, height, interval, frames),
+            AnimationFormat::Apng => write_apng(writer, width, height, interval, frames),
+        }
+        .with_context(|| format!("Failed to write recording {}", path.display()))
+    });
+    (frames, encoder)
+}
+
+/// The frames sent to `rx`, each with the number of frame intervals it is
+/// shown for, which is only known once the next one arrives.
+fn shown_frames(rx: Receiver<Message>) -> impl Iterator<Item = Message> {
+    let mut frames = rx.into_iter().peekable();
+    iter::from_fn(move || {
+        let (rgb, _) = frames.next()?;
+        let intervals = frames.peek().map_or(1, |&(_, intervals)| intervals);
+        Some((rgb, intervals))
+    })
+}
+
+/// Write GIF frames, each with a palette of its own colors.
+fn write_gif<I>(
+    writer: BufWriter<File>,
+    width: u32,
+    height: u32,
+    interval: Fraction,
+    frames: I,
+) -> anyhow::Result<()>
+where
+    I: Iterator<Item = Message>,
+{
+    let width = u16::try_from(width).context("Frames are too wide for a GIF")?;
+    let height = u16::try_from(height).context("Frames are too tall for a GIF")?;
+
+    let mut encoder = gif::Encoder::new(writer, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for (rgb, intervals) in frames {
+        let mut palette = Palette::new(&rgb);
+        let indices = rgb
+            .chunks_exact(3)
+            .map(|pix| palette.index((pix[0], pix[1], pix[2])))
+            .collect::<Vec<_>>();
+        let mut frame = gif::Frame::from_indexed_pixels(width, height, indices, None);
+        frame.palette = Some(palette.to_bytes());
+        // GIF delays are in hundredths of a second
+        let delay = u64::from(interval.numerator) * u64::from(intervals) * 100
+            / u64::from(interval.denominator);
+        frame.delay = u16::try_from(delay).unwrap_or(u16::MAX).max(1);
+        encoder.write_frame(&frame)?;
+    }
+    encoder.into_inner()?;
+    Ok(())
+}
+
+/// Write APNG frames in true color, which needs no palette.
+#[allow(clippy::cast_possible_truncation)]
+fn write_apng<I>(
+    mut writer: BufWriter<File>,
+    width: u32,
+    height: u32,
+    interval: Fraction,
+    frames: I,
+) -> anyhow::Result<()>
+where
+    I: Iterator<Item = Message>,
+{
+    let mut num_frames = 0;
+    let mut encoder = png::Encoder::new(&mut writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    // The number of frames is filled in once they are all written
+    encoder.set_animated(u32::MAX, 0)?;
+    let mut png = encoder.write_header()?;
+    for (rgb, intervals) in frames {
+        let numerator = interval.numerator.saturating_mul(intervals);
+        let (numerator, denominator) = match (
+            u16::try_from(numerator),
+            u16::try_from(interval.denominator),
+        ) {
+            (Ok(numerator), Ok(denominator)) => (numerator, denominator),
+            // Fall back to milliseconds
+            _ => (
+                (u64::from(numerator) * 1000 / u64::from(interval.denominator)).min(u16::MAX.into())
+                    as u16,
+                1000,
+            ),
+        };
+        png.set_frame_delay(numerator, denominator)?;
+        png.write_image_data(&rgb)?;
+        num_frames += 1;
+    }
+    png.finish()?;
+    let mut file = writer.into_inner().map_err(IntoInnerError::into_error)?;
+    set_num_frames(&mut file, num_frames)
+}
+
+/// Set the number of frames in the animation control chunk of the APNG
+/// `file`.
+fn set_num_frames(file: &mut File, num_frames: u32) -> anyhow::Result<()> {
+    // Chunks follow the 8-byte signature, each a 4-byte length and type, its
+    // data and a 4-byte CRC
+    let mut pos = 8;
+    loop {
+        let mut header = [0; 8];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut header)?;
+        let (len, kind) = header.split_at(4);
+        match kind {
+            b"acTL" => break,
+            b"IEND" => return Err(anyhow!("Missing APNG animation control")),
+            _ => pos += 12 + u64::from(u32::from_be_bytes(len.try_into()?)),
+        }
+    }
+    let mut chunk = *b"acTL\0\0\0\0\0\0\0\0";
+    file.read_exact(&mut chunk[4..])?;
+    chunk[4..8].copy_from_slice(&num_frames.to_be_bytes());
+    file.seek(SeekFrom::Start(pos + 4))?;
+    file.write_all(&chunk)?;
+    file.write_all(&png_crc(&chunk).to_be_bytes())?;
+    Ok(())
+}
+
+/// The CRC of a PNG chunk's type and data.
+fn png_crc(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(u32::MAX, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ PNG_CRC
+            }
+        })
+    })
+}
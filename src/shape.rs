@@ -0,0 +1,203 @@
+use crate::pixfmt::Pixels;
+use crate::{AsciiMap, GlyphMap};
+
+/// Cells and glyphs are compared as `GRID` x `GRID` block averages.
+const GRID: u32 = 4;
+const GRID_LEN: usize = (GRID * GRID) as usize;
+/// How much a brightness mismatch counts against a glyph relative to a
+/// shape mismatch.
+const BRIGHTNESS_WEIGHT: f32 = 0.1;
+
+/// How `AsciiFilter` picks the character for a cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GlyphMatching {
+    /// Look up the cell's average brightness in the charset.
+    #[default]
+    Brightness,
+    /// Pick the glyph whose shape best matches the cell's, among those
+    /// close to its brightness.
+    Shape,
+}
+
+#[derive(Debug, Clone)]
+struct Shape {
+    c: char,
+    /// The brightness the charset maps to this glyph.
+    level: f32,
+    /// The glyph's coverage minus its mean, normalized.
+    pattern: [f32; GRID_LEN],
+}
+
+/// Matches cells to glyphs by the sum of squared differences between their
+/// block averages, after scaling each glyph's contrast to best fit the
+/// cell, plus the squared distance between the cell's brightness and the
+/// glyph's place in the charset.
+#[derive(Debug, Clone)]
+pub struct ShapeMatcher {
+    shapes: Vec<Shape>,
+    /// 1 if glyph coverage stands for brightness, -1 if it stands for
+    /// darkness.
+    ink: f32,
+}
+
+impl ShapeMatcher {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn new(ascii_map: &AsciiMap, glyphs: &GlyphMap<'_>) -> Self {
+        let chars = ascii_map.chars();
+        let step = 256.0 / chars.len() as f32;
+        let (shapes, coverages): (Vec<_>, Vec<_>) = chars
+            .iter()
+            .enumerate()
+            .map(|(idx, &c)| {
+                let mut sums = [0.0; GRID_LEN];
+                let mut counts = [0_u32; GRID_LEN];
+                for &(x, y, b) in glyphs.get(&c).map_or(&[][..], |glyph| &glyph.0) {
+                    let col = (x * GRID / glyphs.width.max(1)).min(GRID - 1);
+                    let row = (y * GRID / glyphs.height.max(1)).min(GRID - 1);
+                    let block = (row * GRID + col) as usize;
+                    sums[block] += f32::from(b.value());
+                    counts[block] += 1;
+                }
+                let avgs = sums
+                    .iter()
+                    .zip(counts)
+                    .map(|(sum, count)| sum / count.max(1) as f32);
+                let (pattern, coverage) = normalize(avgs);
+                let shape = Shape {
+                    c,
+                    level: (idx as f32 + 0.5) * step,
+                    pattern,
+                };
+                (shape, coverage)
+            })
+            .unzip();
+
+        let n = shapes.len().max(1) as f32;
+        let mean_level = shapes.iter().map(|shape| shape.level).sum::<f32>() / n;
+        let mean_coverage = coverages.iter().sum::<f32>() / n;
+        let covariance = shapes
+            .iter()
+            .zip(&coverages)
+            .map(|(shape, coverage)| (shape.level - mean_level) * (coverage - mean_coverage))
+            .sum::<f32>();
+        let ink = if covariance < 0.0 { -1.0 } else { 1.0 };
+        Self { shapes, ink }
+    }
+
+    /// The best matching character for the `width` x `height` cell at
+    /// (`x`, `y`), clipped to the frame, after mapping its brightness with
+    /// `tone`.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn best(
+        &self,
+        pixels: &Pixels<'_>,
+        (x, y): (u32, u32),
+        (width, height): (u32, u32),
+        tone: Option<&[u8; 256]>,
+    ) -> char {
+        let width = width.min(pixels.width() - x);
+        let height = height.min(pixels.height() - y);
+        let avgs = (0..GRID * GRID).map(|block| {
+            let (col, row) = (block % GRID, block / GRID);
+            let (x0, x1) = (col * width / GRID, (col + 1) * width / GRID);
+            let (y0, y1) = (row * height / GRID, (row + 1) * height / GRID);
+            let avg = pixels.avg_brightness(x + x0, y + y0, (x1 - x0).max(1), (y1 - y0).max(1));
+            let avg = tone.map_or(avg.value(), |lut| lut[usize::from(avg.value())]);
+            self.ink * f32::from(avg)
+        });
+        let (pattern, mean) = centered(avgs);
+        let mean = self.ink * mean;
+        let cost = |shape: &Shape| {
+            // The fitted glyph's residual is the cell's variance minus this
+            let fit = dot(&pattern, &shape.pattern).max(0.0);
+            BRIGHTNESS_WEIGHT * GRID_LEN as f32 * (mean - shape.level).powi(2) - fit * fit
+        };
+        self.shapes
+            .iter()
+            .min_by(|shape1, shape2| cost(shape1).total_cmp(&cost(shape2)))
+            .map_or(' ', |shape| shape.c)
+    }
+}
+
+/// Subtract the mean of `values`, also returning the mean.
+#[allow(clippy::cast_precision_loss)]
+fn centered<I>(values: I) -> ([f32; GRID_LEN], f32)
+where
+    I: IntoIterator<Item = f32>,
+{
+    let mut centered = [0.0; GRID_LEN];
+    for (dst, value) in centered.iter_mut().zip(values) {
+        *dst = value;
+    }
+    let mean = centered.iter().sum::<f32>() / GRID_LEN as f32;
+    for value in &mut centered {
+        *value -= mean;
+    }
+    (centered, mean)
+}
+
+/// Subtract the mean of `values` and scale them to unit length, also
+/// returning the mean.
+fn normalize<I>(values: I) -> ([f32; GRID_LEN], f32)
+where
+    I: IntoIterator<Item = f32>,
+{
+    let (mut pattern, mean) = centered(values);
+    let norm = dot(&pattern, &pattern).sqrt();
+    if norm > 0.0 {
+        for value in &mut pattern {
+            *value /= norm;
+        }
+    }
+    (pattern, mean)
+}
+
+fn dot(lhs: &[f32; GRID_LEN], rhs: &[f32; GRID_LEN]) -> f32 {
+    lhs.iter().zip(rhs).map(|(l, r)| l * r).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GlyphMapBuilder, PixelFormat};
+
+    const SIZE: u32 = 16;
+
+    /// The character picked for a white cell with black pixels where `ink`
+    /// is true.
+    fn best<F>(ink: F) -> char
+    where
+        F: Fn(u32, u32) -> bool,
+    {
+        let ascii_map = AsciiMap::new(vec!['#', '|', '-', '.', ' ']).unwrap();
+        let glyphs = GlyphMapBuilder::new(ascii_map.chars())
+            .with_size(SIZE)
+            .build()
+            .unwrap();
+        let matcher = ShapeMatcher::new(&ascii_map, &glyphs);
+        let format = PixelFormat::Rgb24;
+        let mut buf = vec![0; format.frame_size(SIZE, SIZE)];
+        for (idx, px) in (0..).zip(buf.chunks_exact_mut(3)) {
+            px.fill(if ink(idx % SIZE, idx / SIZE) { 0 } else { 255 });
+        }
+        let pixels = Pixels::new(&mut buf, SIZE, SIZE, format);
+        matcher.best(&pixels, (0, 0), (SIZE, SIZE), None)
+    }
+
+    // Glyphs are drawn from the top left corner of their cell, so bars
+    // match along the top and left edges
+    #[test]
+    fn horizontal_bar_is_dash() {
+        assert_eq!(best(|_, y| y < SIZE / GRID), '-');
+    }
+
+    #[test]
+    fn vertical_bar_is_pipe() {
+        assert_eq!(best(|x, _| x < SIZE / GRID), '|');
+    }
+
+    #[test]
+    fn blank_cell_is_space() {
+        assert_eq!(best(|_, _| false), ' ');
+    }
+}
@@ -0,0 +1,80 @@
+use crate::Brightness;
+
+/// How far, in levels, a cell's brightness must move past the edge of its
+/// current level before it changes character.
+const HYSTERESIS: f32 = 0.25;
+
+/// Keeps cells from flickering between characters with sensor noise, by
+/// averaging their brightness over frames and holding each one's level
+/// until its brightness clearly leaves it.
+#[derive(Debug, Clone, Default)]
+pub struct Stabilizer {
+    cols: u32,
+    rows: u32,
+    /// Each cell's exponential moving average of brightness.
+    brightness: Vec<f32>,
+    /// Each cell's last level, out of `nlevels`.
+    levels: Vec<usize>,
+    nlevels: usize,
+}
+
+impl Stabilizer {
+    /// Replace the brightness of each of the `cols` x `rows` cells with its
+    /// average over frames, weighting the previous average by `smoothing`.
+    /// Starts over when the grid changes size.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn smooth(&mut self, smoothing: f32, cols: u32, rows: u32, brightness: &mut [Brightness]) {
+        if (cols, rows) != (self.cols, self.rows) || self.brightness.len() != brightness.len() {
+            *self = Self {
+                cols,
+                rows,
+                brightness: brightness.iter().map(|b| f32::from(b.value())).collect(),
+                ..Self::default()
+            };
+            return;
+        }
+        for (avg, b) in self.brightness.iter_mut().zip(brightness) {
+            *avg += (1.0 - smoothing) * (f32::from(b.value()) - *avg);
+            *b = Brightness(avg.round() as u8);
+        }
+    }
+
+    /// The level out of `nlevels` of each of the `cols` x `rows` cells of
+    /// `brightness`, keeping a cell's previous level while it is within
+    /// `HYSTERESIS` of it. Starts over when the grid changes size.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn levels(
+        &mut self,
+        cols: u32,
+        rows: u32,
+        brightness: &[Brightness],
+        nlevels: usize,
+    ) -> Vec<usize> {
+        let step = 256.0 / nlevels as f32;
+        let level = |b: Brightness| usize::from(b.value()) * nlevels / 256;
+        if (cols, rows) != (self.cols, self.rows) {
+            *self = Self {
+                cols,
+                rows,
+                ..Self::default()
+            };
+        }
+        if nlevels != self.nlevels || self.levels.len() != brightness.len() {
+            self.nlevels = nlevels;
+            self.levels = brightness.iter().copied().map(level).collect();
+        } else {
+            for (prev, &b) in self.levels.iter_mut().zip(brightness) {
+                let value = f32::from(b.value());
+                let (low, high) = (*prev as f32 * step, (*prev + 1) as f32 * step);
+                if value < low - HYSTERESIS * step || value >= high + HYSTERESIS * step {
+                    *prev = level(b);
+                }
+            }
+        }
+        self.levels.clone()
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::Write;
+
+use anyhow::Context;
+
+use crate::{AsciiCell, AsciiFilter, Frame, FrameSink, PixelFormat};
+
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// How `TextSink` colors characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextColor {
+    Plain,
+    /// The 6x6x6 color cube of the 256-color palette.
+    Ansi256,
+    /// 24-bit color.
+    TrueColor,
+}
+
+impl TextColor {
+    /// The escape sequence setting the foreground color to `rgb`, if any.
+    fn escape(self, (red, green, blue): (u8, u8, u8)) -> Option<String> {
+        let level = |c: u8| (u16::from(c) * 5 + 127) / 255;
+        match self {
+            Self::Plain => None,
+            Self::Ansi256 => Some(format!(
+                "\x1b[38;5;{}m",
+                16 + 36 * level(red) + 6 * level(green) + level(blue)
+            )),
+            Self::TrueColor => Some(format!("\x1b[38;2;{red};{green};{blue}m")),
+        }
+    }
+}
+
+/// Writes frames as text, one line per row of glyphs and a blank line after
+/// each frame.
+pub struct TextSink<'font, W> {
+    writer: W,
+    ascii_filter: AsciiFilter<'font>,
+    color: TextColor,
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    buf: Vec<u8>,
+}
+
+impl<'font, W> TextSink<'font, W>
+where
+    W: Write,
+{
+    /// Map `width` x `height` frames to text with `ascii_filter`'s charset,
+    /// glyph size and mode.
+    pub fn new(
+        writer: W,
+        ascii_filter: AsciiFilter<'font>,
+        color: TextColor,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        // Colors are averaged over each cell, so keep full chroma
+        let format = PixelFormat::Rgb24;
+        Self {
+            writer,
+            ascii_filter,
+            color,
+            width,
+            height,
+            format,
+            buf: vec![0; format.frame_size(width, height)],
+        }
+    }
+
+    fn write_row(&mut self, row: &[AsciiCell]) -> std::io::Result<()> {
+        let mut line = String::new();
+        let mut last_rgb = None;
+        for cell in row {
+            let rgb = cell.color();
+            if last_rgb != Some(rgb) {
+                if let Some(escape) = self.color.escape(rgb) {
+                    line.push_str(&escape);
+                }
+                last_rgb = Some(rgb);
+            }
+            line.push(cell.c);
+        }
+        if self.color != TextColor::Plain {
+            line.push_str(ANSI_RESET);
+        }
+        writeln!(self.writer, "{line}")
+    }
+}
+
+impl<W> FrameSink for TextSink<'_, W>
+where
+    W: Write,
+{
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn write_frame(&mut self, frame: &Frame<'_>) -> anyhow::Result<()> {
+        let mut text_frame =
+            Frame::with_format(&mut self.buf, self.width, self.height, self.format);
+        frame.convert_into(&mut text_frame);
+        let (cell_width, cell_height) = self.ascii_filter.size();
+        let grid = self
+            .ascii_filter
+            .to_grid(&text_frame, cell_width, cell_height);
+        grid.iter_rows()
+            .try_for_each(|row| self.write_row(row))
+            .and_then(|()| writeln!(self.writer))
+            .and_then(|()| self.writer.flush())
+            .context("Failed to write text frame")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsciiMap, AsciiMode, Brightness, GlyphMapBuilder};
+
+    const RED: (u8, u8, u8) = (255, 0, 0);
+    const BLUE: (u8, u8, u8) = (0, 0, 255);
+
+    fn sink(color: TextColor) -> TextSink<'static, Vec<u8>> {
+        let ascii_map = AsciiMap::new(vec!['#', '.']).unwrap();
+        let glyphs = GlyphMapBuilder::new(ascii_map.chars()).build().unwrap();
+        let ascii_filter = AsciiFilter::new(ascii_map, glyphs, AsciiMode::Color);
+        TextSink::new(Vec::new(), ascii_filter, color, 4, 4)
+    }
+
+    /// The bytes written for a row of `a` and `b` in red then `c` in blue.
+    fn row_bytes(color: TextColor) -> String {
+        let cell = |c, rgb| AsciiCell {
+            c,
+            brightness: Brightness(0),
+            rgb: Some(rgb),
+        };
+        let mut sink = sink(color);
+        sink.write_row(&[cell('a', RED), cell('b', RED), cell('c', BLUE)])
+            .unwrap();
+        String::from_utf8(sink.writer).unwrap()
+    }
+
+    #[test]
+    fn ansi256_escapes() {
+        let escape = |rgb| TextColor::Ansi256.escape(rgb).unwrap();
+        assert_eq!(escape((0, 0, 0)), "\x1b[38;5;16m");
+        assert_eq!(escape(RED), "\x1b[38;5;196m");
+        assert_eq!(escape(BLUE), "\x1b[38;5;21m");
+        assert_eq!(escape((128, 128, 128)), "\x1b[38;5;145m");
+        assert_eq!(escape((255, 255, 255)), "\x1b[38;5;231m");
+    }
+
+    #[test]
+    fn truecolor_escapes() {
+        let escape = |rgb| TextColor::TrueColor.escape(rgb).unwrap();
+        assert_eq!(escape((1, 2, 3)), "\x1b[38;2;1;2;3m");
+        assert_eq!(escape((255, 255, 255)), "\x1b[38;2;255;255;255m");
+        assert_eq!(TextColor::Plain.escape(RED), None);
+    }
+
+    #[test]
+    fn rows_escape_color_changes() {
+        assert_eq!(row_bytes(TextColor::Plain), "abc\n");
+        assert_eq!(
+            row_bytes(TextColor::Ansi256),
+            "\x1b[38;5;196mab\x1b[38;5;21mc\x1b[0m\n"
+        );
+        assert_eq!(
+            row_bytes(TextColor::TrueColor),
+            "\x1b[38;2;255;0;0mab\x1b[38;2;0;0;255mc\x1b[0m\n"
+        );
+    }
+}
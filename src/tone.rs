@@ -0,0 +1,153 @@
+/// Adjustments to the brightness of cells before they are mapped to
+/// characters, applied in the order levels, gamma, contrast, brightness.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMap {
+    brightness: i32,
+    contrast: f32,
+    gamma: f32,
+    black_point: u8,
+    white_point: u8,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ToneMap {
+    /// Leave the brightness unchanged.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            brightness: 0,
+            contrast: 1.0,
+            gamma: 1.0,
+            black_point: 0,
+            white_point: u8::MAX,
+        }
+    }
+
+    /// Add `brightness`, from -255 to 255.
+    #[must_use]
+    pub fn with_brightness(mut self, brightness: i32) -> Self {
+        self.brightness = brightness.clamp(-255, 255);
+        self
+    }
+
+    /// Scale the distance from mid-gray by `contrast`, 0 or more.
+    #[must_use]
+    pub const fn with_contrast(mut self, contrast: f32) -> Self {
+        self.contrast = contrast.max(0.0);
+        self
+    }
+
+    /// Raise the brightness to the power of 1 / `gamma`, so that gammas
+    /// above 1 brighten the midtones.
+    #[must_use]
+    pub const fn with_gamma(mut self, gamma: f32) -> Self {
+        self.gamma = gamma.max(0.1);
+        self
+    }
+
+    /// Stretch the range from `black_point` to `white_point` to the full
+    /// range, clipping what is outside.
+    #[must_use]
+    pub fn with_levels(mut self, black_point: u8, white_point: u8) -> Self {
+        self.white_point = white_point.max(1);
+        self.black_point = black_point.min(self.white_point - 1);
+        self
+    }
+
+    #[must_use]
+    pub const fn brightness(&self) -> i32 {
+        self.brightness
+    }
+
+    #[must_use]
+    pub const fn contrast(&self) -> f32 {
+        self.contrast
+    }
+
+    #[must_use]
+    pub const fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    /// The black and white points.
+    #[must_use]
+    pub const fn levels(&self) -> (u8, u8) {
+        (self.black_point, self.white_point)
+    }
+
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        *self == Self::new()
+    }
+
+    /// The adjusted value of every brightness.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    #[must_use]
+    pub fn lut(&self) -> [u8; 256] {
+        let black = f32::from(self.black_point);
+        let range = f32::from(self.white_point) - black;
+        let mut lut = [0; 256];
+        for (value, dst) in (0_u8..=u8::MAX).zip(&mut lut) {
+            let level = ((f32::from(value) - black) / range).clamp(0.0, 1.0);
+            let level = level.powf(self.gamma.recip());
+            let level = (level - 0.5) * self.contrast + 0.5;
+            let value = level * 255.0 + self.brightness as f32;
+            *dst = value.round().clamp(0.0, 255.0) as u8;
+        }
+        lut
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_lut() {
+        let lut = ToneMap::new().lut();
+        assert!((0..=u8::MAX).all(|value| lut[usize::from(value)] == value));
+    }
+
+    #[test]
+    fn brightness_shifts_and_clips() {
+        let lut = ToneMap::new().with_brightness(40).lut();
+        assert_eq!((lut[0], lut[100], lut[255]), (40, 140, 255));
+        let lut = ToneMap::new().with_brightness(-40).lut();
+        assert_eq!((lut[0], lut[100], lut[255]), (0, 60, 215));
+    }
+
+    #[test]
+    fn contrast_pivots_on_mid_gray() {
+        let lut = ToneMap::new().with_contrast(2.0).lut();
+        assert_eq!((lut[0], lut[63], lut[192], lut[255]), (0, 0, 255, 255));
+        let lut = ToneMap::new().with_contrast(0.0).lut();
+        assert!(lut.iter().all(|&value| value == 128));
+    }
+
+    #[test]
+    fn gamma_keeps_endpoints() {
+        let lut = ToneMap::new().with_gamma(2.2).lut();
+        assert_eq!((lut[0], lut[255]), (0, 255));
+        assert!(lut[64] > 64);
+        let lut = ToneMap::new().with_gamma(0.5).lut();
+        assert_eq!((lut[0], lut[255]), (0, 255));
+        assert!(lut[64] < 64);
+    }
+
+    #[test]
+    fn levels_clip_outside_points() {
+        let lut = ToneMap::new().with_levels(50, 200).lut();
+        assert!(lut[..=50].iter().all(|&value| value == 0));
+        assert!(lut[200..].iter().all(|&value| value == 255));
+        assert_eq!(lut[125], 128);
+    }
+}
@@ -0,0 +1,113 @@
+use asciime_filter::{
+    AsciiFilter, AsciiMap, AsciiMode, BlockCharset, Frame, GlyphMapBuilder, PixelFormat,
+};
+
+const CELL_SIZE: u32 = 8;
+
+/// The character of a single `CELL_SIZE` cell that is white except for the
+/// black pixels for which `is_dark` holds.
+fn cell_char<F>(ascii_map: AsciiMap, is_dark: F) -> char
+where
+    F: Fn(u32, u32) -> bool,
+{
+    let mut buf = (0..CELL_SIZE)
+        .flat_map(|y| (0..CELL_SIZE).map(move |x| (x, y)))
+        .flat_map(|(x, y)| [if is_dark(x, y) { 0 } else { u8::MAX }; 3])
+        .collect::<Vec<_>>();
+    let frame = Frame::with_format(&mut buf, CELL_SIZE, CELL_SIZE, PixelFormat::Rgb24);
+    let glyphs = GlyphMapBuilder::new(ascii_map.chars())
+        .with_size(CELL_SIZE)
+        .build()
+        .unwrap();
+    let mut ascii_filter = AsciiFilter::new(ascii_map, glyphs, AsciiMode::Grayscale);
+    let grid = ascii_filter.to_grid(&frame, CELL_SIZE, CELL_SIZE);
+    grid.get(0, 0).unwrap().c
+}
+
+#[test]
+fn new_rejects_invalid_charsets() {
+    let err = AsciiMap::new(vec![]).unwrap_err();
+    assert_eq!(err.to_string(), "The charset is empty");
+
+    let too_long = (0..257).filter_map(|c| char::from_u32(0x100 + c)).collect();
+    let err = AsciiMap::new(too_long).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The charset has 257 characters, expected at most 256"
+    );
+
+    let err = AsciiMap::new(vec!['#', '.', '#']).unwrap_err();
+    assert_eq!(err.to_string(), "The charset has '#' more than once");
+
+    assert_eq!(AsciiMap::new(vec!['#']).unwrap().chars(), ['#']);
+}
+
+#[test]
+fn half_block_bits() {
+    let half_blocks = AsciiMap::from_blocks(BlockCharset::HalfBlocks);
+    assert_eq!(half_blocks.subcells(), Some((1, 2)));
+    assert_eq!(half_blocks.chars(), [' ', '▀', '▄', '█']);
+}
+
+#[test]
+fn quadrant_bits() {
+    let quadrants = AsciiMap::from_blocks(BlockCharset::Quadrants);
+    assert_eq!(quadrants.subcells(), Some((2, 2)));
+    let chars = quadrants.chars();
+    // Bits are the upper left, upper right, lower left and lower right
+    for (bits, c) in [
+        (0b0000, ' '),
+        (0b0001, '▘'),
+        (0b0010, '▝'),
+        (0b0100, '▖'),
+        (0b1000, '▗'),
+        (0b0011, '▀'),
+        (0b1100, '▄'),
+        (0b0101, '▌'),
+        (0b1010, '▐'),
+        (0b1001, '▚'),
+        (0b0110, '▞'),
+        (0b1111, '█'),
+    ] {
+        assert_eq!(chars[bits], c, "{bits:#06b}");
+    }
+}
+
+#[test]
+fn blocks_follow_dark_subcells() {
+    let half = CELL_SIZE / 2;
+    let quadrant = cell_char(AsciiMap::from_blocks(BlockCharset::Quadrants), |x, y| {
+        x < half && y < half
+    });
+    assert_eq!(quadrant, '▘');
+    let half_block = cell_char(AsciiMap::from_blocks(BlockCharset::HalfBlocks), |_, y| {
+        y >= half
+    });
+    assert_eq!(half_block, '▄');
+}
+
+#[test]
+fn braille_bits() {
+    let braille = AsciiMap::braille();
+    assert_eq!(braille.subcells(), Some((2, 4)));
+    let chars = braille.chars();
+    assert_eq!(chars.len(), 256);
+    assert_eq!(chars[0], '\u{2800}');
+    assert_eq!(chars[0xff], '\u{28ff}');
+    // Bits are in reading order, dots numbered down the left column then
+    // the right one, then along the bottom row
+    for (bit, dot) in [0, 3, 1, 4, 2, 5, 6, 7].into_iter().enumerate() {
+        assert_eq!(
+            chars[1 << bit],
+            char::from_u32(0x2800 + (1 << dot)).unwrap()
+        );
+    }
+}
+
+#[test]
+fn braille_follows_dark_subcells() {
+    let half = CELL_SIZE / 2;
+    // The dot in the right column of the second row
+    let braille = cell_char(AsciiMap::braille(), |x, y| x >= half && (2..4).contains(&y));
+    assert_eq!(braille, '\u{2810}');
+}
@@ -0,0 +1,90 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use asciime_filter::{
+    FileSink, FileSource, Frame, FrameSink, FrameSource, Pattern, PatternSource, PixelFormat,
+};
+use v4l::Fraction;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 36;
+const NFRAMES: usize = 3;
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("asciime-{}-{name}", std::process::id()))
+}
+
+/// Write `NFRAMES` frames of a moving test pattern to `sink`, returning them.
+fn write_frames(sink: &mut FileSink, format: PixelFormat) -> Vec<Vec<u8>> {
+    let mut source = PatternSource::new(Pattern::Checkerboard, WIDTH, HEIGHT)
+        .with_format(format)
+        .with_interval(Fraction::new(1, 1000));
+    (0..NFRAMES)
+        .map(|_| {
+            let mut buf = vec![0; format.frame_size(WIDTH, HEIGHT)];
+            assert!(source.read_frame(&mut buf).unwrap());
+            sink.write_frame(&Frame::with_format(&mut buf, WIDTH, HEIGHT, format))
+                .unwrap();
+            buf
+        })
+        .collect()
+}
+
+/// Read back the frames `source` has, checking there are as many as
+/// `expected`.
+fn assert_frames(source: &mut FileSource, expected: &[Vec<u8>]) {
+    for frame in expected {
+        let mut buf = vec![0; frame.len()];
+        assert!(source.read_frame(&mut buf).unwrap());
+        assert_eq!(&buf, frame);
+    }
+    let mut buf = vec![0; expected[0].len()];
+    assert!(!source.read_frame(&mut buf).unwrap());
+}
+
+#[test]
+fn y4m_round_trip() {
+    let path = temp_path("round-trip.y4m");
+    let format = PixelFormat::I420;
+    let interval = Fraction::new(1, 25);
+    let frames = {
+        let mut sink = FileSink::y4m(&path, WIDTH, HEIGHT, format, interval).unwrap();
+        write_frames(&mut sink, format)
+    };
+
+    let mut source = FileSource::y4m(&path).unwrap();
+    assert_eq!(
+        (source.width(), source.height(), source.format()),
+        (WIDTH, HEIGHT, format)
+    );
+    let read_interval = source.interval();
+    assert_eq!(
+        (read_interval.numerator, read_interval.denominator),
+        (interval.numerator, interval.denominator)
+    );
+    assert_frames(&mut source, &frames);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn raw_round_trip() {
+    let path = temp_path("round-trip.yuv");
+    let format = PixelFormat::Yuyv;
+    let frames = {
+        let mut sink = FileSink::raw(&path, WIDTH, HEIGHT, format).unwrap();
+        write_frames(&mut sink, format)
+    };
+
+    let mut source = FileSource::raw(&path, WIDTH, HEIGHT, format, Fraction::new(1, 1000)).unwrap();
+    assert_frames(&mut source, &frames);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn y4m_rejects_malformed_header() {
+    let path = temp_path("malformed.y4m");
+    fs::write(&path, "YUV4MPEG2 W64 H36 \u{e9}x F30:1\nFRAME\n").unwrap();
+    assert!(FileSource::y4m(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
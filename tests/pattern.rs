@@ -0,0 +1,63 @@
+use asciime_filter::{FrameSource, Pattern, PatternSource, PixelFormat};
+use v4l::Fraction;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+/// The next frame of `source`, as RGB24 bytes.
+fn read_rgb(source: &mut PatternSource) -> Vec<u8> {
+    let mut buf = vec![0; PixelFormat::Rgb24.frame_size(WIDTH, HEIGHT)];
+    assert!(source.read_frame(&mut buf).unwrap());
+    buf
+}
+
+fn rgb_source(pattern: Pattern) -> PatternSource {
+    PatternSource::new(pattern, WIDTH, HEIGHT)
+        .with_format(PixelFormat::Rgb24)
+        .with_interval(Fraction::new(1, 1000))
+}
+
+fn pixel(buf: &[u8], x: u32, y: u32) -> &[u8] {
+    let idx = 3 * (y * WIDTH + x) as usize;
+    &buf[idx..idx + 3]
+}
+
+#[test]
+fn patterns_parse_by_name() {
+    for pattern in Pattern::ALL {
+        assert_eq!(pattern.name().parse::<Pattern>().unwrap(), pattern);
+    }
+    let err = "stripes".parse::<Pattern>().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unknown pattern stripes, expected one of: bars, gradient, checkerboard, zoneplate, noise"
+    );
+}
+
+#[test]
+fn checkerboard_moves_diagonally() {
+    let mut source = rgb_source(Pattern::Checkerboard);
+    let first = read_rgb(&mut source);
+    let second = read_rgb(&mut source);
+    assert_ne!(first, second);
+    for y in 1..HEIGHT {
+        for x in 1..WIDTH {
+            assert_eq!(
+                pixel(&second, x - 1, y - 1),
+                pixel(&first, x, y),
+                "({x}, {y})"
+            );
+        }
+    }
+}
+
+#[test]
+fn color_bars_corners() {
+    let mut source = rgb_source(Pattern::ColorBars);
+    let frame = read_rgb(&mut source);
+    assert_eq!(pixel(&frame, 0, 0), [191, 191, 191]);
+    assert_eq!(pixel(&frame, WIDTH - 1, 0), [0, 0, 191]);
+    assert_eq!(pixel(&frame, WIDTH - 1, HEIGHT - 1), [0, 0, 0]);
+    // Still patterns render the same every frame
+    assert_eq!(read_rgb(&mut source), frame);
+}
@@ -0,0 +1,59 @@
+use std::env;
+use std::fs;
+
+use asciime_filter::{
+    charset, AsciiFilter, AsciiMap, AsciiMode, FileSink, FileSource, Frame, FrameFilter,
+    FrameSource, GlyphMapBuilder, Pattern, PatternSource, PixelFormat, StreamProcessor,
+};
+use v4l::Fraction;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+const NFRAMES: usize = 3;
+
+fn ascii_filter() -> AsciiFilter<'static> {
+    let chars = charset(4).unwrap();
+    let glyphs = GlyphMapBuilder::new(&chars).with_size(8).build().unwrap();
+    AsciiFilter::new(AsciiMap::new(chars).unwrap(), glyphs, AsciiMode::Grayscale)
+}
+
+fn pattern_source() -> PatternSource {
+    PatternSource::new(Pattern::Checkerboard, WIDTH, HEIGHT).with_interval(Fraction::new(1, 1000))
+}
+
+#[test]
+fn pattern_to_file() {
+    let path = env::temp_dir().join(format!("asciime-{}-pipeline.yuv", std::process::id()));
+    let format = PixelFormat::Yuyv;
+    let sink = FileSink::raw(&path, WIDTH, HEIGHT, format).unwrap();
+    let mut stream =
+        StreamProcessor::new(pattern_source(), sink).add_filter(Box::new(ascii_filter()));
+    for _ in 0..NFRAMES {
+        assert!(stream.process_frame().unwrap());
+    }
+    drop(stream);
+
+    // The file has the pattern's frames as filtered on their own
+    let mut expected_source = pattern_source();
+    let mut expected_filter = ascii_filter();
+    let mut output = FileSource::raw(&path, WIDTH, HEIGHT, format, Fraction::new(1, 1000)).unwrap();
+    for _ in 0..NFRAMES {
+        let mut original = vec![0; format.frame_size(WIDTH, HEIGHT)];
+        assert!(expected_source.read_frame(&mut original).unwrap());
+        let mut expected = original.clone();
+        expected_filter.process(&mut Frame::with_format(
+            &mut expected,
+            WIDTH,
+            HEIGHT,
+            format,
+        ));
+        assert_ne!(expected, original);
+
+        let mut buf = vec![0; format.frame_size(WIDTH, HEIGHT)];
+        assert!(output.read_frame(&mut buf).unwrap());
+        assert_eq!(buf, expected);
+    }
+    let mut buf = vec![0; format.frame_size(WIDTH, HEIGHT)];
+    assert!(!output.read_frame(&mut buf).unwrap());
+    fs::remove_file(&path).unwrap();
+}